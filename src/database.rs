@@ -8,193 +8,504 @@ Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 =============================================================================== */
 
 use once_cell::sync::OnceCell;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicBool};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
 
 use crate::settings as set;
+use crate::lang::{Lang, Key, t};
+use crate::error::{Error, Result};
+use crate::pg_tls::{self, TlsMode};
+use crate::metrics;
+use crate::tasks;
+use crate::ftn::{addr, areas::Area, charset::{self, Charset}};
 
 // Database
-pub static DB: OnceCell<tokio_postgres::Client> = OnceCell::new();
+static DB: OnceCell<Pool> = OnceCell::new();
+
+// Result of the last health check or query
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+// Tables are checked and settings are loaded
+static PREPARED: AtomicBool = AtomicBool::new(false);
+
+// Delay between health checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Limit of delay between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Create the pool, connections are opened on demand.
+// Without the mode from the environment sslmode of the URL is used, by default prefer
+pub fn connect(url: &str, tls_mode: Option<TlsMode>, ca_path: Option<&str>, size: usize) -> Result<()> {
+   let mut config = url.parse::<tokio_postgres::Config>()?;
+   let tls_mode = tls_mode.unwrap_or_else(|| TlsMode::from_ssl_mode(config.get_ssl_mode()));
+   config.ssl_mode(tls_mode.ssl_mode());
+   let tls = pg_tls::connector(tls_mode, ca_path)?;
+
+   // Verified method checks the connection before reuse
+   let manager = Manager::from_config(config, tls, ManagerConfig {
+      recycling_method: RecyclingMethod::Verified,
+   });
+
+   let pool = Pool::builder(manager)
+   .max_size(size)
+   .runtime(Runtime::Tokio1)
+   .wait_timeout(Some(Duration::from_secs(10)))
+   .create_timeout(Some(Duration::from_secs(10)))
+   .recycle_timeout(Some(Duration::from_secs(5)))
+   .build()
+   .map_err(|_| Error::Settings("cannot build database pool"))?;
+
+   DB.set(pool).map_err(|_| Error::Settings("database already connected"))
+}
+
+// Connection from the pool, fails at once while the database is unavailable
+async fn client() -> Result<deadpool_postgres::Client> {
+   if !AVAILABLE.load(atomic::Ordering::Relaxed) {
+      return Err(Error::Unavailable);
+   }
+
+   let pool = DB.get().ok_or(Error::Unavailable)?;
+   pool.get().await.map_err(|e| {
+      log::info!("Database is unavailable: {}", e);
+      AVAILABLE.store(false, atomic::Ordering::Relaxed);
+      Error::from(e)
+   })
+}
+
+// Connected and prepared, otherwise the bot works without the database
+pub fn is_available() -> bool {
+   AVAILABLE.load(atomic::Ordering::Relaxed) && PREPARED.load(atomic::Ordering::Relaxed)
+}
+
+// Check the database bypassing the availability flag
+pub async fn ping() -> Result<()> {
+   let timer = metrics::DB_LATENCY.start_timer();
+   let pool = DB.get().ok_or(Error::Unavailable)?;
+   let client = pool.get().await?;
+   client.simple_query("SELECT 1").await?;
+   timer.observe_duration();
+   Ok(())
+}
+
+// Wait for the database with growing delay between attempts
+pub async fn wait_available() {
+   let mut backoff = Duration::from_secs(1);
+   loop {
+      match ping().await {
+         Ok(()) => {
+            if !AVAILABLE.load(atomic::Ordering::Relaxed) {
+               log::info!("Database connected");
+            }
+            AVAILABLE.store(true, atomic::Ordering::Relaxed);
+            return;
+         }
+         Err(e) => {
+            log::info!("Database connection error, retry in {} sec: {}", backoff.as_secs(), e);
+            AVAILABLE.store(false, atomic::Ordering::Relaxed);
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+         }
+      }
+   }
+}
+
+// Periodic health check, restores the availability after failures
+pub async fn monitor() {
+   loop {
+      tokio::time::sleep(CHECK_INTERVAL).await;
+      wait_available().await;
+   }
+}
 
 struct User {
    descr: Option<String>,
    addr: Option<String>,
-   last_seen: i32,
+}
+
+// User activity in the particular chat
+struct Seen {
+   last_seen: DateTime<Utc>,
    num_short_announcements: i32,
 }
 
-// Announcement text for the user, if necessary
-pub async fn announcement(user_id: i64, time: i32) -> Option<String> {
+// Netmail delivered to the user, for the reply
+pub struct NetmailLink {
+   pub msgid: String,
+   pub from_name: String,
+   pub from_addr: String,
+   pub to_addr: String,
+   pub subject: String,
+}
+
+// Group chat known to the bot
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Chat {
+   pub chat_id: i64,
+   pub title: String,
+}
+
+// Announcement text for the user in the chat, if necessary
+pub async fn announcement(user_id: i64, chat_id: i64, time: DateTime<Utc>, lang: Lang) -> Result<Option<String>> {
+
+   let user = match load_user(user_id).await? {
+      Some(user) => user,
+      None => {
+         // Remember a new user
+         save_new_user(user_id, time).await?;
+         save_new_seen(user_id, chat_id, time).await?;
+         return Ok(None);
+      }
+   };
 
-   match load_user(user_id).await {
-      Some(user) => {
+   match load_seen(user_id, chat_id).await? {
+      Some(seen) => {
          // If enough time has passed
-         if (time - user.last_seen) as u32 > set::interval() {
-            update_user_time(user_id, time).await;
-
-            let mut addr = user.addr.unwrap_or(String::default());
-
-            if user.num_short_announcements >= 12 {
-               reset_num_short_announcements(user_id).await;
-            } else {
-               addr = addr.split(",").take(2).collect();
-            };
-
-            // Ask about updates
-            tokio::spawn(request_addr(user_id));
-            
-            let res = if addr == "" {
-               user.descr.unwrap_or(String::from("БОФА"))
-            } else {
-               format!("{} {}", addr, user.descr.unwrap_or_default())
-            };
-
-            Some(res)
+         if time - seen.last_seen > set::interval(chat_id) {
+            update_user_time(user_id, chat_id, time).await?;
+
+            // Full address sometimes
+            let short = seen.num_short_announcements < 12;
+            if !short {
+               reset_num_short_announcements(user_id, chat_id).await?;
+            }
+
+            // Ask about updates, the lookup should finish before exit
+            tasks::spawn(async move {
+               let res = request_addr(user_id).await;
+               metrics::addr_lookup(res.is_ok());
+               if let Err(e) = res {
+                  log::info!("request_addr error for {}: {}", user_id, e);
+               }
+            });
+
+            Ok(Some(announcement_text(user.addr.as_deref(), user.descr.as_deref(), short, lang)))
          } else {
             // To small time elapsed
-            None
+            Ok(None)
          }
       }
       None => {
-         // Remember a new user
-         save_new_user(user_id, time).await;
-         None
+         // First message of the user in this chat
+         save_new_seen(user_id, chat_id, time).await?;
+         Ok(None)
       }
    }
 }
 
+// Text of announcement from the nodelist address and the origin
+fn announcement_text(addr: Option<&str>, descr: Option<&str>, short: bool, lang: Lang) -> String {
+   let addr = addr.unwrap_or_default();
+   let addr: String = if short {
+      addr.split(',').take(2).collect()
+   } else {
+      String::from(addr)
+   };
+
+   if addr.is_empty() {
+      String::from(descr.unwrap_or_else(|| t(lang, Key::NoOrigin)))
+   } else {
+      format!("{} {}", addr, descr.unwrap_or_default())
+   }
+}
+
+// Short and full announcements with the new origin, as they will appear in chat
+pub async fn announcement_preview(user_id: i64, descr: &str, lang: Lang) -> Result<(String, String)> {
+   let addr = load_user(user_id).await?.and_then(|user| user.addr);
+   let addr = addr.as_deref();
+   Ok((announcement_text(addr, Some(descr), true, lang), announcement_text(addr, Some(descr), false, lang)))
+}
+
+// Guaranteed to save the user in the database without announcement
+pub async fn register_user(user_id: i64, time: DateTime<Utc>) -> Result<()> {
+   if load_user(user_id).await?.is_none() {
+      save_new_user(user_id, time).await?;
+   }
+   Ok(())
+}
+
+// Modifying queries of this module should affect exactly one record
+fn one_row(name: &'static str, n: u64) -> Result<()> {
+   match n {
+      1 => Ok(()),
+      n => Err(Error::RowCount(name, n)),
+   }
+}
+
 // Создаёт таблицы, если её ещё не существует
-pub async fn check_database() {
+pub async fn check_database() -> Result<()> {
    // Получаем клиента БД
-   let client = DB.get().unwrap();
+   let client = client().await?;
 
    // Выполняем запрос
-   let rows = client.query("SELECT table_name FROM INFORMATION_SCHEMA.TABLES WHERE TABLE_NAME='users'", &[]).await.unwrap();
+   let rows = client.query("SELECT table_name FROM INFORMATION_SCHEMA.TABLES WHERE TABLE_NAME='users'", &[]).await?;
 
    // Если таблица не существует, создадим её
    if rows.is_empty() {
       log::info!("Create database");
 
-      let query = client.batch_execute("CREATE TABLE users (
+      client.batch_execute("CREATE TABLE users (
          PRIMARY KEY (user_id),
          user_id        BIGINT         NOT NULL,
          descr          VARCHAR(100),
          addr           VARCHAR(100),
-         last_seen      INTEGER        NOT NULL,
+         last_seen      TIMESTAMPTZ    NOT NULL,
          num_short_announcements INTEGER NOT NULL
       );
 
-      CREATE TABLE settings (announcement_delta INTEGER);
+      CREATE TABLE settings (announcement_delta BIGINT);
       INSERT INTO settings (announcement_delta) VALUES (30);
       ")
-      .await;
-
-      if let Err(e) = query {
-         log::info!("check_database create error: {}", e)
-      }
+      .await?;
    } else {
       log::info!("Database exists");
    }
 
-   // Init settings
-   let data = client.query_one("SELECT announcement_delta FROM settings", &[]).await;
-
-   if let Err(_) = data.map_err(|_| ()).and_then(|row| set::init_interval(row.get(0))) {
-      log::info!("check_database() Error load settings");
+   // Tables for multi-chat support
+   client.batch_execute("CREATE TABLE IF NOT EXISTS chats (
+      PRIMARY KEY (chat_id),
+      chat_id        BIGINT         NOT NULL,
+      title          VARCHAR(255)   NOT NULL,
+      announcement_delta BIGINT     NOT NULL
+   );
+
+   CREATE TABLE IF NOT EXISTS seen (
+      PRIMARY KEY (user_id, chat_id),
+      user_id        BIGINT         NOT NULL,
+      chat_id        BIGINT         NOT NULL,
+      last_seen      TIMESTAMPTZ    NOT NULL,
+      num_short_announcements INTEGER NOT NULL
+   );
+
+   ALTER TABLE users ADD COLUMN IF NOT EXISTS lang VARCHAR(2);
+
+   CREATE TABLE IF NOT EXISTS dialogues (
+      PRIMARY KEY (chat_id),
+      chat_id        BIGINT         NOT NULL,
+      dialogue       BYTEA          NOT NULL
+   );
+
+   CREATE TABLE IF NOT EXISTS ftn_messages (
+      PRIMARY KEY (chat_id, message_id),
+      chat_id        BIGINT         NOT NULL,
+      message_id     INTEGER        NOT NULL,
+      msgid          VARCHAR(128)   NOT NULL
+   );
+   CREATE INDEX IF NOT EXISTS ftn_messages_msgid ON ftn_messages (msgid);
+   ALTER TABLE ftn_messages ADD COLUMN IF NOT EXISTS area VARCHAR(64);
+   ALTER TABLE settings ADD COLUMN IF NOT EXISTS ftn_serial BIGINT NOT NULL DEFAULT 0;
+
+   CREATE TABLE IF NOT EXISTS netmail (
+      PRIMARY KEY (chat_id, message_id),
+      chat_id        BIGINT         NOT NULL,
+      message_id     INTEGER        NOT NULL,
+      msgid          VARCHAR(128)   NOT NULL,
+      from_name      VARCHAR(36)    NOT NULL,
+      from_addr      VARCHAR(32)    NOT NULL,
+      to_addr        VARCHAR(32)    NOT NULL,
+      subject        VARCHAR(72)    NOT NULL
+   );
+
+   CREATE TABLE IF NOT EXISTS areas (
+      PRIMARY KEY (tag),
+      tag            VARCHAR(64)    NOT NULL,
+      descr          VARCHAR(100)   NOT NULL,
+      uplink         VARCHAR(32)    NOT NULL,
+      read_only      BOOLEAN        NOT NULL,
+      chat_id        BIGINT         NOT NULL,
+      thread_id      INTEGER
+   );
+   ALTER TABLE areas ADD COLUMN IF NOT EXISTS quote BOOLEAN NOT NULL DEFAULT TRUE;
+   ALTER TABLE areas ADD COLUMN IF NOT EXISTS greeting VARCHAR(100) NOT NULL DEFAULT '';
+
+   CREATE TABLE IF NOT EXISTS dupes (
+      PRIMARY KEY (area, hash),
+      area           VARCHAR(64)    NOT NULL,
+      hash           CHAR(32)       NOT NULL,
+      msgid          VARCHAR(128)   NOT NULL,
+      seen           TIMESTAMPTZ    NOT NULL,
+      hits           INTEGER        NOT NULL
+   );
+   CREATE INDEX IF NOT EXISTS dupes_msgid ON dupes (area, msgid);
+   CREATE INDEX IF NOT EXISTS dupes_seen ON dupes (seen);
+   ")
+   .await?;
+
+   // Earlier versions stored seconds since epoch in 32 bits
+   migrate_to_timestamp(&client, "users", "last_seen").await?;
+   migrate_to_timestamp(&client, "seen", "last_seen").await?;
+   migrate_to_bigint(&client, "settings", "announcement_delta").await?;
+   migrate_to_bigint(&client, "chats", "announcement_delta").await?;
+
+   // Init settings, the global interval is the default for new chats
+   let row = client.query_one("SELECT announcement_delta FROM settings", &[]).await?;
+   set::init_interval(chrono::Duration::seconds(row.get(0)))?;
+
+   log::info!("Default interval for announcements {} sec", set::default_interval().num_seconds());
+
+   // Per-chat settings
+   let rows = client.query("SELECT chat_id, announcement_delta FROM chats", &[]).await?;
+   for row in rows {
+      let chat_id: i64 = row.get(0);
+      let interval: i64 = row.get(1);
+      set::init_chat_interval(chat_id, chrono::Duration::seconds(interval))?;
+      log::info!("Interval for announcements in {} is {} sec", chat_id, interval);
    }
 
-   log::info!("Interval for announcements {} sec", set::interval());
+   PREPARED.store(true, atomic::Ordering::Relaxed);
+   Ok(())
 }
 
-async fn load_user(id: i64) -> Option<User> {
-   let client = DB.get().unwrap();
-   let query = client.query("SELECT descr, addr, last_seen, num_short_announcements FROM users WHERE user_id=$1::BIGINT", &[&id]).await;
+// Type of the column in lower case
+async fn column_type(client: &tokio_postgres::Client, table: &str, column: &str) -> Result<Option<String>> {
+   let row = client.query_opt("SELECT data_type FROM INFORMATION_SCHEMA.COLUMNS WHERE table_name = $1 AND column_name = $2", &[&table, &column]).await?;
+   Ok(row.map(|row| row.get::<_, String>(0).to_lowercase()))
+}
 
-   match query {
-      Ok(data) => {
-         match data.len() {
-            1 => Some(User{
-               // id,
-               descr: data[0].get(0),
-               addr: data[0].get(1),
-               last_seen: data[0].get(2),
-               num_short_announcements: data[0].get(3),
-            }),
-            _ => None,
-         }
+// Convert seconds since epoch to timestamp with time zone
+async fn migrate_to_timestamp(client: &tokio_postgres::Client, table: &str, column: &str) -> Result<()> {
+   if column_type(client, table, column).await?.as_deref() == Some("integer") {
+      log::info!("Migrate {}.{} to TIMESTAMPTZ", table, column);
+      let query = format!("ALTER TABLE {table} ALTER COLUMN {column} TYPE TIMESTAMPTZ USING to_timestamp({column})", table = table, column = column);
+      client.batch_execute(&query).await?;
+   }
+   Ok(())
+}
 
-      }
-      Err(e) => {
-         log::info!("load_user error: {}, {}", id, e);
-         None
-      }
+// Widen seconds of the interval
+async fn migrate_to_bigint(client: &tokio_postgres::Client, table: &str, column: &str) -> Result<()> {
+   if column_type(client, table, column).await?.as_deref() == Some("integer") {
+      log::info!("Migrate {}.{} to BIGINT", table, column);
+      let query = format!("ALTER TABLE {table} ALTER COLUMN {column} TYPE BIGINT", table = table, column = column);
+      client.batch_execute(&query).await?;
    }
+   Ok(())
 }
 
-pub async fn update_user_time(id: i64, time: i32) {
-   let client = DB.get().unwrap();
-   let query = client.execute("UPDATE users SET last_seen = $1::INTEGER, num_short_announcements = num_short_announcements + 1 WHERE user_id = $2::BIGINT", &[&time, &id]).await;
+async fn load_user(id: i64) -> Result<Option<User>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT descr, addr FROM users WHERE user_id=$1::BIGINT", &[&id]).await?;
 
-   match query {
-      Ok(1) => (),
-      Ok(n) => log::info!("update_user_time error: {}, {} - updated {} records", id, time, n),
-      Err(e) => log::info!("update_user_time error: {}, {} - {}", id, time, e),
-   }
+   Ok(row.map(|row| User{
+      // id,
+      descr: row.get(0),
+      addr: row.get(1),
+   }))
 }
 
-pub async fn reset_num_short_announcements(id: i64) {
-   let client = DB.get().unwrap();
-   let query = client.execute("UPDATE users SET num_short_announcements = 0 WHERE user_id = $1::BIGINT", &[&id]).await;
+async fn load_seen(user_id: i64, chat_id: i64) -> Result<Option<Seen>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT last_seen, num_short_announcements FROM seen WHERE user_id=$1::BIGINT AND chat_id=$2::BIGINT", &[&user_id, &chat_id]).await?;
 
-   match query {
-      Ok(1) => (),
-      Ok(n) => log::info!("reset_num_short_announcements error: {} - updated {} records", id, n),
-      Err(e) => log::info!("reset_num_short_announcements error: {} - {}", id, e),
-   }
+   Ok(row.map(|row| Seen{
+      last_seen: row.get(0),
+      num_short_announcements: row.get(1),
+   }))
 }
 
-pub async fn save_new_user(id: i64, time: i32) {
-   let client = DB.get().unwrap();
-   let query = client.execute("INSERT INTO users (user_id, last_seen, num_short_announcements) VALUES ($1::BIGINT, $2::INTEGER, 0)", &[&id, &time]).await;
+async fn save_new_seen(user_id: i64, chat_id: i64, time: DateTime<Utc>) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("INSERT INTO seen (user_id, chat_id, last_seen, num_short_announcements) VALUES ($1::BIGINT, $2::BIGINT, $3::TIMESTAMPTZ, 0)", &[&user_id, &chat_id, &time]).await?;
+   one_row("save_new_seen", n)
+}
 
-   match query {
-      Ok(1) => (),
-      Ok(n) => log::info!("update_user_time error: {}, {} - updated {} records", id, time, n),
-      Err(e) => log::info!("update_user_time error: {}, {} - {}", id, time, e),
-   }
+pub async fn update_user_time(user_id: i64, chat_id: i64, time: DateTime<Utc>) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE seen SET last_seen = $1::TIMESTAMPTZ, num_short_announcements = num_short_announcements + 1 WHERE user_id = $2::BIGINT AND chat_id = $3::BIGINT", &[&time, &user_id, &chat_id]).await?;
+   one_row("update_user_time", n)
 }
 
-pub async fn user_descr(id: i64) -> String {
-   match load_user(id).await {
-      Some(user) => if user.addr.is_some() {format!("{}\n{}", user.addr.unwrap(), user.descr.unwrap_or_default())} else {user.descr.unwrap_or_default()},
+pub async fn reset_num_short_announcements(user_id: i64, chat_id: i64) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE seen SET num_short_announcements = 0 WHERE user_id = $1::BIGINT AND chat_id = $2::BIGINT", &[&user_id, &chat_id]).await?;
+   one_row("reset_num_short_announcements", n)
+}
+
+pub async fn save_new_user(id: i64, time: DateTime<Utc>) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("INSERT INTO users (user_id, last_seen, num_short_announcements) VALUES ($1::BIGINT, $2::TIMESTAMPTZ, 0)", &[&id, &time]).await?;
+   one_row("save_new_user", n)
+}
+
+pub async fn user_descr(id: i64) -> Result<String> {
+   let res = match load_user(id).await? {
+      Some(user) => match user.addr {
+         Some(addr) => format!("{}\n{}", addr, user.descr.unwrap_or_default()),
+         None => user.descr.unwrap_or_default(),
+      },
       None => String::default(),
-   }
+   };
+   Ok(res)
 }
 
-pub async fn update_user_descr(id: i64, descr: &str) {
-   let client = DB.get().unwrap();
-   let query = client.execute("UPDATE users SET descr = $1::VARCHAR(100) WHERE user_id = $2::BIGINT", &[&descr, &id]).await;
+// Origin and nodelist address of the user
+pub async fn user_origin(id: i64) -> Result<(Option<String>, Option<String>)> {
+   Ok(load_user(id).await?.map_or((None, None), |user| (user.descr, user.addr)))
+}
 
-   match query {
-      Ok(1) => (),
-      Ok(n) => log::info!("update_user_descr error: {}, {} - updated {} records", id, descr, n),
-      Err(e) => log::info!("update_user_descr error: {}, {} - {}", id, descr, e),
-   }
+// Users with filled address or origin
+pub async fn users_list() -> Result<Vec<String>> {
+   let client = client().await?;
+   let rows = client.query("SELECT addr, descr FROM users WHERE addr IS NOT NULL OR descr IS NOT NULL ORDER BY addr", &[]).await?;
+
+   Ok(rows.iter().map(|row| {
+      let addr: Option<String> = row.get(0);
+      let descr: Option<String> = row.get(1);
+      format!("{} {}", addr.unwrap_or_default(), descr.unwrap_or_default()).trim().to_string()
+   }).collect())
 }
 
-pub async fn update_interval(i: i32) -> Result<(), ()> {
-   let client = DB.get().unwrap();
-   let query = client.execute("UPDATE settings SET announcement_delta = $1::INTEGER", &[&i]).await;
+pub async fn user_lang(id: i64) -> Result<Option<Lang>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT lang FROM users WHERE user_id = $1::BIGINT", &[&id]).await?;
 
-   match query {
-      Ok(1) => Ok(()),
-      Ok(n) => {log::info!("update_interval error: {} - updated {} records", i, n); Err(())},
-      Err(e) => {log::info!("update_interval error: {} - {}", i, e); Err(())},
-   }
+   Ok(row
+   .and_then(|row| row.get::<_, Option<String>>(0))
+   .and_then(|code| Lang::from_code(&code)))
+}
+
+pub async fn update_user_lang(id: i64, lang: Lang) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE users SET lang = $1::VARCHAR(2) WHERE user_id = $2::BIGINT", &[&lang.code(), &id]).await?;
+   one_row("update_user_lang", n)
+}
+
+pub async fn update_user_descr(id: i64, descr: &str) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE users SET descr = $1::VARCHAR(100) WHERE user_id = $2::BIGINT", &[&descr, &id]).await?;
+   one_row("update_user_descr", n)
+}
+
+pub async fn update_interval(chat_id: i64, interval: chrono::Duration) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE chats SET announcement_delta = $1::BIGINT WHERE chat_id = $2::BIGINT", &[&interval.num_seconds(), &chat_id]).await?;
+   one_row("update_interval", n)
+}
+
+// Remember the group chat with the default settings
+pub async fn save_new_chat(chat_id: i64, title: &str, interval: chrono::Duration) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("INSERT INTO chats (chat_id, title, announcement_delta) VALUES ($1::BIGINT, $2::VARCHAR(255), $3::BIGINT) ON CONFLICT (chat_id) DO UPDATE SET title = EXCLUDED.title", &[&chat_id, &title, &interval.num_seconds()]).await?;
+   one_row("save_new_chat", n)
+}
+
+pub async fn chats() -> Result<Vec<Chat>> {
+   let client = client().await?;
+   let rows = client.query("SELECT chat_id, title FROM chats ORDER BY title", &[]).await?;
+
+   Ok(rows.iter().map(|row| Chat {
+      chat_id: row.get(0),
+      title: row.get(1),
+   }).collect())
 }
 
 #[derive(Deserialize)]
+#[allow(dead_code)]
 struct Node {
    pub addr: String,
    pub name: String,
@@ -216,16 +527,7 @@ impl Node {
 
 impl PartialOrd for Node {
    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-      // Sort by node number and point number
-      let a = self.addr_struct();
-      let b = other.addr_struct();
-      if a.1 == 0 && b.1 > 0 {
-         Some(Ordering::Less)
-      } else if a.1 > 0 && b.1 == 0 {
-         Some(Ordering::Greater)
-      } else {
-         a.partial_cmp(&b)
-      }
+      Some(self.cmp(other))
    }
 }
 
@@ -239,17 +541,26 @@ impl Eq for Node {}
 
 impl Ord for Node {
    fn cmp(&self, other: &Self) -> Ordering {
-      self.partial_cmp(other).unwrap()
+      // Sort by node number and point number
+      let a = self.addr_struct();
+      let b = other.addr_struct();
+      if a.1 == 0 && b.1 > 0 {
+         Ordering::Less
+      } else if a.1 > 0 && b.1 == 0 {
+         Ordering::Greater
+      } else {
+         a.cmp(&b)
+      }
    }
 }
 
 type Nodelist = Vec<Node>;
 
-fn from_nodelist(mut nodelist: Nodelist) -> String {
-   let name = if nodelist.len() > 0 {
+fn from_nodelist(mut nodelist: Nodelist) -> Option<String> {
+   let name = if !nodelist.is_empty() {
       nodelist[0].name.clone()
    } else {
-      return String::from("Ошибка, пустой нодлист");
+      return None;
    };
 
    nodelist.sort();
@@ -260,44 +571,179 @@ fn from_nodelist(mut nodelist: Nodelist) -> String {
    addrs.dedup_by(|a, b| a.starts_with(b.as_str()));
 
    // Remove repeated prefix
-   let mut suffix = addrs.split_off(1).iter().map(|s| s.replace("2:5011/", "/")).collect::<Vec<String>>();
+   let mut suffix = addrs.split_off(1).iter().map(|s| s.replace(addr::HOME_NET, "/")).collect::<Vec<String>>();
    addrs.append(&mut suffix);
 
-   addrs.iter().fold(name, |acc, s| format!("{}, {}", acc, s))
+   Some(addrs.iter().fold(name, |acc, s| format!("{}, {}", acc, s)))
 }
 
-async fn request_addr(user_id: i64) {
+async fn request_addr(user_id: i64) -> Result<()> {
    let url = format!("https://guestl.info/grfidobot/api/v1/users/{}", user_id);
 
-   let req = Client::new()
+   let body = Client::new()
    .get(url)
    .basic_auth("arthome", Some("emminet"))
    .send()
-   .await;
-
-   match req {
-      Ok(req) => {
-         let body = req.json::<Nodelist>().await;
-         match body {
-            Ok(nodelist) => {
-               let s = &from_nodelist(nodelist);
-               log::info!("request_addr updated for {}: {}", user_id, s);
-               update_user_addr(user_id, s).await
-            },
-            Err(e) => log::info!("body error for {}: {}", user_id, e),
-         };
+   .await?
+   .bytes()
+   .await?;
+
+   // Names from old nodelists may come in CP866
+   let (body, _) = charset::decode_auto(&body, Charset::Cp866);
+   let nodelist: Nodelist = serde_json::from_str(&body)?;
+
+   match from_nodelist(nodelist) {
+      Some(s) => {
+         log::info!("request_addr updated for {}: {}", user_id, s);
+         update_user_addr(user_id, &s).await
+      }
+      None => {
+         log::info!("request_addr empty nodelist for {}", user_id);
+         Ok(())
       }
-      Err(e) => log::info!("req error for {}: {}", user_id, e),
    }
 }
-pub async fn update_user_addr(id: i64, addr: &str) {
-   let client = DB.get().unwrap();
-   let query = client.execute("UPDATE users SET addr = $1::VARCHAR(100) WHERE user_id = $2::BIGINT", &[&addr, &id]).await;
 
-   match query {
-      Ok(1) => (),
-      Ok(n) => log::info!("update_user_addr error: {}, {} - updated {} records", id, addr, n),
-      Err(e) => log::info!("update_user_addr error: {}, {} - {}", id, addr, e),
-   }
+pub async fn update_user_addr(id: i64, addr: &str) -> Result<()> {
+   let client = client().await?;
+   let n = client.execute("UPDATE users SET addr = $1::VARCHAR(100) WHERE user_id = $2::BIGINT", &[&addr, &id]).await?;
+   one_row("update_user_addr", n)
+}
+
+// Serialized FSM state of the dialogue in the chat
+pub async fn dialogue(chat_id: i64) -> Result<Option<Vec<u8>>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT dialogue FROM dialogues WHERE chat_id = $1::BIGINT", &[&chat_id]).await?;
+   Ok(row.map(|row| row.get(0)))
 }
 
+pub async fn update_dialogue(chat_id: i64, dialogue: &[u8]) -> Result<()> {
+   let client = client().await?;
+   client.execute("INSERT INTO dialogues (chat_id, dialogue) VALUES ($1::BIGINT, $2::BYTEA) ON CONFLICT (chat_id) DO UPDATE SET dialogue = EXCLUDED.dialogue", &[&chat_id, &dialogue]).await?;
+   Ok(())
+}
+
+pub async fn remove_dialogue(chat_id: i64) -> Result<u64> {
+   let client = client().await?;
+   Ok(client.execute("DELETE FROM dialogues WHERE chat_id = $1::BIGINT", &[&chat_id]).await?)
+}
+
+// Telegram message and its MSGID in the echo area, for threading of replies
+pub async fn save_ftn_message(chat_id: i64, message_id: i32, msgid: &str, area: &str) -> Result<()> {
+   let client = client().await?;
+   client.execute("INSERT INTO ftn_messages (chat_id, message_id, msgid, area) VALUES ($1::BIGINT, $2::INTEGER, $3::VARCHAR(128), $4::VARCHAR(64)) ON CONFLICT (chat_id, message_id) DO UPDATE SET msgid = EXCLUDED.msgid, area = EXCLUDED.area", &[&chat_id, &message_id, &msgid, &area]).await?;
+   Ok(())
+}
+
+// MSGID and the area, earlier versions did not store the area
+pub async fn ftn_message(chat_id: i64, message_id: i32) -> Result<Option<(String, Option<String>)>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT msgid, area FROM ftn_messages WHERE chat_id = $1::BIGINT AND message_id = $2::INTEGER", &[&chat_id, &message_id]).await?;
+   Ok(row.map(|row| (row.get(0), row.get(1))))
+}
+
+// The first part if the message was split
+pub async fn ftn_message_id(chat_id: i64, msgid: &str) -> Result<Option<i32>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT message_id FROM ftn_messages WHERE chat_id = $1::BIGINT AND msgid = $2::VARCHAR(128) ORDER BY message_id LIMIT 1", &[&chat_id, &msgid]).await?;
+   Ok(row.map(|row| row.get(0)))
+}
+
+// Serials of the gateway reserved by the previous runs
+pub async fn ftn_serial() -> Result<i64> {
+   let client = client().await?;
+   let row = client.query_one("SELECT ftn_serial FROM settings", &[]).await?;
+   Ok(row.get(0))
+}
+
+// The stored reservation never goes back
+pub async fn reserve_ftn_serial(upto: i64) -> Result<()> {
+   let client = client().await?;
+   client.execute("UPDATE settings SET ftn_serial = GREATEST(ftn_serial, $1::BIGINT)", &[&upto]).await?;
+   Ok(())
+}
+
+// Nodelist addresses of the users as is
+pub async fn users_addr() -> Result<Vec<(i64, String)>> {
+   let client = client().await?;
+   let rows = client.query("SELECT user_id, addr FROM users WHERE addr IS NOT NULL", &[]).await?;
+   Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+pub async fn save_netmail(chat_id: i64, message_id: i32, link: &NetmailLink) -> Result<()> {
+   let client = client().await?;
+   client.execute("INSERT INTO netmail (chat_id, message_id, msgid, from_name, from_addr, to_addr, subject) VALUES ($1::BIGINT, $2::INTEGER, $3::VARCHAR(128), $4::VARCHAR(36), $5::VARCHAR(32), $6::VARCHAR(32), $7::VARCHAR(72)) ON CONFLICT (chat_id, message_id) DO NOTHING",
+      &[&chat_id, &message_id, &link.msgid, &link.from_name, &link.from_addr, &link.to_addr, &link.subject]).await?;
+   Ok(())
+}
+
+pub async fn netmail(chat_id: i64, message_id: i32) -> Result<Option<NetmailLink>> {
+   let client = client().await?;
+   let row = client.query_opt("SELECT msgid, from_name, from_addr, to_addr, subject FROM netmail WHERE chat_id = $1::BIGINT AND message_id = $2::INTEGER", &[&chat_id, &message_id]).await?;
+   Ok(row.map(|row| NetmailLink {
+      msgid: row.get(0),
+      from_name: row.get(1),
+      from_addr: row.get(2),
+      to_addr: row.get(3),
+      subject: row.get(4),
+   }))
+}
+
+// Echo areas, the uplink is skipped if it is no longer valid
+pub async fn areas() -> Result<Vec<Area>> {
+   let client = client().await?;
+   let rows = client.query("SELECT tag, descr, uplink, read_only, chat_id, thread_id, quote, greeting FROM areas ORDER BY tag", &[]).await?;
+   Ok(rows.iter().filter_map(|row| Some(Area {
+      tag: row.get(0),
+      descr: row.get(1),
+      uplink: row.get::<_, String>(2).parse().ok()?,
+      read_only: row.get(3),
+      chat_id: row.get(4),
+      thread_id: row.get(5),
+      quote: row.get(6),
+      greeting: row.get(7),
+   })).collect())
+}
+
+pub async fn save_area(area: &Area) -> Result<()> {
+   let client = client().await?;
+   client.execute("INSERT INTO areas (tag, descr, uplink, read_only, chat_id, thread_id, quote, greeting) VALUES ($1::VARCHAR(64), $2::VARCHAR(100), $3::VARCHAR(32), $4::BOOLEAN, $5::BIGINT, $6::INTEGER, $7::BOOLEAN, $8::VARCHAR(100))
+      ON CONFLICT (tag) DO UPDATE SET descr = EXCLUDED.descr, uplink = EXCLUDED.uplink, read_only = EXCLUDED.read_only, chat_id = EXCLUDED.chat_id, thread_id = EXCLUDED.thread_id, quote = EXCLUDED.quote, greeting = EXCLUDED.greeting",
+      &[&area.tag, &area.descr, &area.uplink.to_string(), &area.read_only, &area.chat_id, &area.thread_id, &area.quote, &area.greeting]).await?;
+   Ok(())
+}
+
+// False if there was no such area
+pub async fn delete_area(tag: &str) -> Result<bool> {
+   let client = client().await?;
+   let count = client.execute("DELETE FROM areas WHERE tag = $1::VARCHAR(64)", &[&tag]).await?;
+   Ok(count > 0)
+}
+
+// The same MSGID or content in the area, the hit is counted
+pub async fn find_dupe(area: &str, msgid: &str, hash: &str) -> Result<bool> {
+   let client = client().await?;
+   let count = client.execute("UPDATE dupes SET hits = hits + 1 WHERE area = $1::VARCHAR(64) AND (hash = $2::CHAR(32) OR (msgid <> '' AND msgid = $3::VARCHAR(128)))",
+      &[&area, &hash, &msgid]).await?;
+   Ok(count > 0)
+}
+
+pub async fn save_dupe(area: &str, msgid: &str, hash: &str, time: DateTime<Utc>) -> Result<()> {
+   let client = client().await?;
+   client.execute("INSERT INTO dupes (area, hash, msgid, seen, hits) VALUES ($1::VARCHAR(64), $2::CHAR(32), $3::VARCHAR(128), $4::TIMESTAMPTZ, 0) ON CONFLICT (area, hash) DO NOTHING",
+      &[&area, &hash, &msgid, &time]).await?;
+   Ok(())
+}
+
+pub async fn delete_old_dupes(before: DateTime<Utc>) -> Result<u64> {
+   let client = client().await?;
+   let count = client.execute("DELETE FROM dupes WHERE seen < $1::TIMESTAMPTZ", &[&before]).await?;
+   Ok(count)
+}
+
+// Areas with duplicates and their number
+pub async fn dupe_hits() -> Result<Vec<(String, i64)>> {
+   let client = client().await?;
+   let rows = client.query("SELECT area, SUM(hits)::BIGINT FROM dupes GROUP BY area HAVING SUM(hits) > 0 ORDER BY area", &[]).await?;
+   Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
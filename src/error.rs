@@ -0,0 +1,72 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Errors. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use derive_more::{Display, From};
+use teloxide::RequestError;
+
+// Failures of storage, network and Telegram
+#[derive(Debug, Display, From)]
+pub enum Error {
+   #[display(fmt = "database error: {}", _0)]
+   Database(tokio_postgres::Error),
+
+   // Name of the function and the number of affected rows instead of one
+   #[display(fmt = "database error: {} affected {} records", _0, _1)]
+   #[from(ignore)]
+   RowCount(&'static str, u64),
+
+   #[display(fmt = "database pool error: {}", _0)]
+   Pool(deadpool_postgres::PoolError),
+
+   #[display(fmt = "database is unavailable")]
+   Unavailable,
+
+   #[display(fmt = "tls error: {}", _0)]
+   Tls(native_tls::Error),
+
+   #[display(fmt = "io error: {}", _0)]
+   Io(std::io::Error),
+
+   #[display(fmt = "network error: {}", _0)]
+   Network(reqwest::Error),
+
+   #[display(fmt = "json error: {}", _0)]
+   Json(serde_json::Error),
+
+   #[display(fmt = "telegram error: {}", _0)]
+   Telegram(RequestError),
+
+   #[display(fmt = "settings error: {}", _0)]
+   #[from(ignore)]
+   Settings(&'static str),
+
+   // Malformed FidoNet address, packet or message
+   #[display(fmt = "ftn error: {}", _0)]
+   #[from(ignore)]
+   Ftn(&'static str),
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+   // Outages that pass by themselves, the operation is worth repeating later
+   pub fn is_transient(&self) -> bool {
+      match self {
+         Error::Database(_) | Error::Pool(_) | Error::Unavailable | Error::Io(_) | Error::Network(_) => true,
+         Error::Telegram(e) => match e {
+            RequestError::NetworkError(_) | RequestError::RetryAfter(_) | RequestError::Io(_) => true,
+            RequestError::ApiError { status_code, .. } => status_code.is_server_error(),
+            _ => false,
+         },
+         _ => false,
+      }
+   }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
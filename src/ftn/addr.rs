@@ -0,0 +1,125 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+FidoNet address. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{fmt, str::FromStr};
+
+use crate::error::{Error, Result};
+
+// Net of the channel, its nodes are written as "/N" in the nodelist addresses of users
+pub const HOME_NET: &str = "2:5011/";
+
+// 4D address zone:net/node.point
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Addr {
+   pub zone: u16,
+   pub net: u16,
+   pub node: u16,
+   pub point: u16,
+}
+
+impl Addr {
+   pub fn new(zone: u16, net: u16, node: u16, point: u16) -> Self {
+      Self { zone, net, node, point }
+   }
+
+   // The node itself for a point
+   pub fn boss(&self) -> Self {
+      Self { point: 0, ..*self }
+   }
+
+   // First address in free text like "Name, 2:5011/1, /2.3"
+   pub fn find(text: &str) -> Option<Self> {
+      text.split(|c: char| c == ',' || c.is_whitespace())
+      .find_map(|s| s.parse().ok())
+   }
+
+   // All addresses of such text, "/2.3" is in the home net whatever comes before
+   pub fn find_all(text: &str) -> Vec<Self> {
+      text.split(|c: char| c == ',' || c.is_whitespace())
+      .filter_map(|s| match s.strip_prefix('/') {
+         Some(rest) => format!("{}{}", HOME_NET, rest).parse().ok(),
+         None => s.parse().ok(),
+      })
+      .collect()
+   }
+}
+
+impl fmt::Display for Addr {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}:{}/{}", self.zone, self.net, self.node)?;
+      if self.point != 0 {
+         write!(f, ".{}", self.point)?;
+      }
+      Ok(())
+   }
+}
+
+impl FromStr for Addr {
+   type Err = Error;
+
+   // Domain after @ is ignored
+   fn from_str(s: &str) -> Result<Self> {
+      const INVALID: Error = Error::Ftn("address is not zone:net/node[.point]");
+
+      let s = s.trim();
+      let s = s.split('@').next().unwrap_or_default();
+      let (zone, rest) = s.split_once(':').ok_or(INVALID)?;
+      let (net, rest) = rest.split_once('/').ok_or(INVALID)?;
+      let (node, point) = match rest.split_once('.') {
+         Some((node, point)) => (node, point),
+         None => (rest, "0"),
+      };
+
+      let number = |s: &str| s.parse::<u16>().map_err(|_| INVALID);
+      Ok(Self::new(number(zone)?, number(net)?, number(node)?, number(point)?))
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn parse() {
+      assert_eq!("2:5011/1".parse::<Addr>().unwrap(), Addr::new(2, 5011, 1, 0));
+      assert_eq!(" 2:5011/1.5@fidonet ".parse::<Addr>().unwrap(), Addr::new(2, 5011, 1, 5));
+      assert!("5011/1".parse::<Addr>().is_err());
+      assert!("2:5011".parse::<Addr>().is_err());
+      assert!("2:5011/x".parse::<Addr>().is_err());
+      assert!("2:5011/1.70000".parse::<Addr>().is_err());
+   }
+
+   #[test]
+   fn display() {
+      assert_eq!(Addr::new(2, 5011, 1, 0).to_string(), "2:5011/1");
+      assert_eq!(Addr::new(2, 5011, 1, 5).to_string(), "2:5011/1.5");
+      assert_eq!(Addr::new(2, 5011, 1, 5).boss(), Addr::new(2, 5011, 1, 0));
+   }
+
+   #[test]
+   fn find() {
+      assert_eq!(Addr::find("Artem Khomenko, 2:5011/1.5, /2"), Some(Addr::new(2, 5011, 1, 5)));
+      assert_eq!(Addr::find("2:5011/1 0123abcd"), Some(Addr::new(2, 5011, 1, 0)));
+      assert_eq!(Addr::find("no address"), None);
+   }
+
+   #[test]
+   fn find_all() {
+      assert_eq!(Addr::find_all("2:5011/1, /2.3"), vec![
+         Addr::new(2, 5011, 1, 0),
+         Addr::new(2, 5011, 2, 3),
+      ]);
+      // Short form is in the home net even after an address of another net
+      assert_eq!(Addr::find_all("Name, 2:5020/100, /5"), vec![
+         Addr::new(2, 5020, 100, 0),
+         Addr::new(2, 5011, 5, 0),
+      ]);
+      assert_eq!(Addr::find_all("/2 2:5011/1"), vec![Addr::new(2, 5011, 2, 0), Addr::new(2, 5011, 1, 0)]);
+   }
+}
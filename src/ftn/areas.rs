@@ -0,0 +1,190 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Echo areas and their chats. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use once_cell::sync::Lazy;
+use std::{fmt, sync::RwLock};
+
+use super::{Addr, gateway};
+use crate::database as db;
+use crate::error::{Error, Result};
+
+// Limits of the columns in the database
+const TAG_MAX_LEN: usize = 64;
+const DESCR_MAX_LEN: usize = 100;
+const GREETING_MAX_LEN: usize = 100;
+
+// Echo area in the chat or in the forum topic of the chat
+#[derive(Clone, Debug, PartialEq)]
+pub struct Area {
+   pub tag: String,
+   pub descr: String,
+   pub uplink: Addr,
+   pub read_only: bool,
+   pub chat_id: i64,
+   pub thread_id: Option<i32>,
+   pub quote: bool,
+   pub greeting: String,
+}
+
+// Known areas, the copy of the table
+static AREAS: Lazy<RwLock<Vec<Area>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+impl fmt::Display for Area {
+   // The same form as the input of the admin
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{} {}", self.tag, self.chat_id)?;
+      if let Some(thread_id) = self.thread_id {
+         write!(f, "/{}", thread_id)?;
+      }
+      write!(f, " {}", self.uplink)?;
+      if self.read_only {
+         write!(f, " ro")?;
+      }
+      if !self.quote {
+         write!(f, " noquote")?;
+      }
+      if !self.descr.is_empty() {
+         write!(f, " {}", self.descr)?;
+      }
+      if !self.greeting.is_empty() {
+         write!(f, " | {}", self.greeting)?;
+      }
+      Ok(())
+   }
+}
+
+impl Area {
+   // "TAG chat_id[/topic] [uplink] [ro] [noquote] description | greeting",
+   // the uplink of the gateway by default
+   pub fn parse(s: &str, default_uplink: Addr) -> Option<Self> {
+      let (s, greeting) = s.split_once('|').unwrap_or((s, ""));
+      let greeting: String = greeting.trim().chars().take(GREETING_MAX_LEN).collect();
+
+      let mut words = s.split_whitespace().peekable();
+      let tag = words.next()?.to_uppercase();
+      if tag.len() > TAG_MAX_LEN || !tag.chars().all(|c| c.is_ascii_graphic()) {
+         return None;
+      }
+
+      let chat = words.next()?;
+      let (chat_id, thread_id) = match chat.split_once('/') {
+         Some((chat_id, thread_id)) => (chat_id.parse().ok()?, Some(thread_id.parse().ok()?)),
+         None => (chat.parse().ok()?, None),
+      };
+
+      let uplink = match words.peek().and_then(|w| w.parse().ok()) {
+         Some(uplink) => {
+            words.next();
+            uplink
+         }
+         None => default_uplink,
+      };
+
+      // Flags in any order
+      let (mut read_only, mut quote) = (false, true);
+      while let Some(word) = words.peek() {
+         if word.eq_ignore_ascii_case("ro") {
+            read_only = true;
+         } else if word.eq_ignore_ascii_case("noquote") {
+            quote = false;
+         } else {
+            break;
+         }
+         words.next();
+      }
+
+      let descr: String = words.collect::<Vec<_>>().join(" ").chars().take(DESCR_MAX_LEN).collect();
+      Some(Self { tag, descr, uplink, read_only, chat_id, thread_id, quote, greeting })
+   }
+}
+
+// Areas from the database, the area of FTN_AREA and FTN_CHAT_ID for the empty table
+pub async fn init() -> Result<()> {
+   let config = match gateway::config() {
+      Some(config) => config,
+      None => return Ok(()),
+   };
+
+   let mut areas = db::areas().await?;
+   if areas.is_empty() {
+      if let (Some(tag), Some(chat_id)) = (&config.area, config.chat_id) {
+         let area = Area {
+            tag: tag.clone(),
+            descr: String::new(),
+            uplink: config.uplink,
+            read_only: false,
+            chat_id,
+            thread_id: None,
+            quote: true,
+            greeting: String::new(),
+         };
+         db::save_area(&area).await?;
+         areas.push(area);
+      }
+   }
+
+   for area in &areas {
+      log::info!("Area {}", area);
+   }
+   *AREAS.write().map_err(|_| Error::Settings("areas lock poisoned"))? = areas;
+   Ok(())
+}
+
+pub fn all() -> Vec<Area> {
+   AREAS.read().unwrap().clone()
+}
+
+pub fn by_tag(tag: &str) -> Option<Area> {
+   AREAS.read().unwrap()
+   .iter()
+   .find(|area| area.tag.eq_ignore_ascii_case(tag))
+   .cloned()
+}
+
+pub fn is_area_chat(chat_id: i64) -> bool {
+   AREAS.read().unwrap().iter().any(|area| area.chat_id == chat_id)
+}
+
+// Area of the message by the chat, in forums by the first message of the topic,
+// replies to known messages are resolved by the gateway
+pub fn for_message(chat_id: i64, reply_to: Option<i32>) -> Option<Area> {
+   let areas = AREAS.read().unwrap();
+   let in_chat = || areas.iter().filter(|area| area.chat_id == chat_id);
+
+   in_chat().find(|area| area.thread_id.is_some() && area.thread_id == reply_to)
+   .or_else(|| in_chat().find(|area| area.thread_id.is_none()))
+   .cloned()
+}
+
+// Database first, so that memory is consistent with it
+pub async fn save(area: Area) -> Result<()> {
+   db::save_area(&area).await?;
+
+   let mut areas = AREAS.write().map_err(|_| Error::Settings("areas lock poisoned"))?;
+   match areas.iter_mut().find(|a| a.tag == area.tag) {
+      Some(a) => *a = area,
+      None => {
+         areas.push(area);
+         areas.sort_by(|a, b| a.tag.cmp(&b.tag));
+      }
+   }
+   Ok(())
+}
+
+// False for the unknown area
+pub async fn delete(tag: &str) -> Result<bool> {
+   let tag = tag.to_uppercase();
+   if !db::delete_area(&tag).await? {
+      return Ok(false);
+   }
+
+   AREAS.write().map_err(|_| Error::Settings("areas lock poisoned"))?
+   .retain(|area| area.tag != tag);
+   Ok(true)
+}
@@ -0,0 +1,635 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Binkp sessions, FTS-1026 and FTS-1027. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{path::{Path, PathBuf}, sync::atomic::{AtomicBool, Ordering}, time::Duration};
+use hmac::{Hmac, KeyInit, Mac};
+use md5::Md5;
+use tokio::{
+   fs::File,
+   io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+   net::{TcpListener, TcpStream},
+   sync::{Mutex, Semaphore},
+};
+
+use super::{Addr, areas, bso::{self, FlowEntry}, gateway::{self, Link}};
+use crate::error::{Error, Result};
+use crate::secret;
+
+// Commands of the protocol
+const M_NUL: u8 = 0;
+const M_ADR: u8 = 1;
+const M_PWD: u8 = 2;
+const M_FILE: u8 = 3;
+const M_OK: u8 = 4;
+const M_EOB: u8 = 5;
+const M_GOT: u8 = 6;
+const M_ERR: u8 = 7;
+const M_BSY: u8 = 8;
+const M_GET: u8 = 9;
+const M_SKIP: u8 = 10;
+
+// Longest data block
+const BLOCK_LEN: usize = 4096;
+
+// Incoming sessions at the same time, others get M_BSY
+const MAX_SESSIONS: usize = 8;
+
+// Silence of the remote side before the session is dropped
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub const DEFAULT_PORT: u16 = 24554;
+
+enum Frame {
+   Command(u8, String),
+   Data(Vec<u8>),
+}
+
+// Both sides of the session are the same, except the beginning
+pub struct Session {
+   pub our: Addr,
+   pub remote: Addr,
+   pub password: String,
+}
+
+// Files sent and received
+#[derive(Default)]
+pub struct Stats {
+   pub sent: usize,
+   pub received: usize,
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> Result<Frame> {
+   let read = async {
+      let header = r.read_u16().await?;
+      let len = (header & 0x7fff) as usize;
+      let mut data = vec![0u8; len];
+      r.read_exact(&mut data).await?;
+      Ok::<_, std::io::Error>((header, data))
+   };
+
+   let (header, data) = tokio::time::timeout(IDLE_TIMEOUT, read).await
+   .map_err(|_| Error::Ftn("binkp timeout"))??;
+
+   if header & 0x8000 == 0 {
+      return Ok(Frame::Data(data));
+   }
+
+   let (command, arg) = data.split_first().ok_or(Error::Ftn("binkp empty command"))?;
+   let arg = String::from_utf8_lossy(arg).trim_end_matches('\0').to_string();
+   Ok(Frame::Command(*command, arg))
+}
+
+async fn write_command<W: AsyncWrite + Unpin>(w: &mut W, command: u8, arg: &str) -> Result<()> {
+   let len = arg.len() + 1;
+   w.write_u16(0x8000 | len as u16).await?;
+   w.write_u8(command).await?;
+   w.write_all(arg.as_bytes()).await?;
+   w.flush().await?;
+   Ok(())
+}
+
+async fn write_data<W: AsyncWrite + Unpin>(w: &mut W, data: &[u8]) -> Result<()> {
+   w.write_u16(data.len() as u16).await?;
+   w.write_all(data).await?;
+   Ok(())
+}
+
+// Answer to the CRAM-MD5 challenge in hex
+fn cram_md5(password: &str, challenge: &str) -> Result<String> {
+   let challenge = from_hex(challenge).ok_or(Error::Ftn("binkp invalid challenge"))?;
+   let mut mac = Hmac::<Md5>::new_from_slice(password.as_bytes()).map_err(|_| Error::Ftn("binkp invalid password"))?;
+   mac.update(&challenge);
+   Ok(to_hex(&mac.finalize().into_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+   bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+   if !s.len().is_multiple_of(2) {
+      return None;
+   }
+   (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Addresses of M_ADR like "2:5011/1.0@fidonet 2:5011/1"
+fn parse_addrs(arg: &str) -> Vec<Addr> {
+   arg.split_whitespace().filter_map(|s| s.parse().ok()).collect()
+}
+
+// Our file from M_GOT or M_SKIP like "name size time"
+fn acknowledge(pending: &std::sync::Mutex<Vec<(String, FlowEntry)>>, arg: &str) -> Option<FlowEntry> {
+   let name = arg.split_whitespace().next()?;
+   let mut pending = pending.lock().unwrap();
+   let pos = pending.iter().position(|(n, _)| n == name)?;
+   Some(pending.remove(pos).1)
+}
+
+// Only the name without path and escapes, binkp sends spaces as \x20
+fn safe_name(name: &str) -> Option<String> {
+   let mut res = String::new();
+   let mut chars = name.chars();
+   while let Some(c) = chars.next() {
+      if c == '\\' && chars.clone().next() == Some('x') {
+         chars.next();
+         let hex: String = chars.by_ref().take(2).collect();
+         let byte = u8::from_str_radix(&hex, 16).ok()?;
+         res.push(byte as char);
+      } else {
+         res.push(c);
+      }
+   }
+   let res = res.replace(['/', '\\', ':'], "_");
+   if res.is_empty() || res.starts_with('.') { None } else { Some(res) }
+}
+
+// Free name in the inbound, the file with the same name may be not processed yet
+fn free_name(dir: &Path, name: &str) -> PathBuf {
+   let (stem, ext) = match name.rsplit_once('.') {
+      Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+      _ => (name, String::new()),
+   };
+   std::iter::once(dir.join(name))
+   .chain((1..).map(|n| dir.join(format!("{}-{}{}", stem, n, ext))))
+   .find(|path| !path.exists())
+   .unwrap_or_default()
+}
+
+// The answering side offers the CRAM-MD5 challenge
+async fn send_info<W: AsyncWrite + Unpin>(w: &mut W, our: Addr, challenge: Option<&str>) -> Result<()> {
+   if let Some(challenge) = challenge {
+      write_command(w, M_NUL, &format!("OPT CRAM-MD5-{}", challenge)).await?;
+   }
+   write_command(w, M_NUL, "SYS N5011_bot").await?;
+   write_command(w, M_NUL, "ZYZ Telegram gateway").await?;
+   write_command(w, M_NUL, &format!("VER N5011_bot/{} binkp/1.0", env!("CARGO_PKG_VERSION"))).await?;
+   write_command(w, M_ADR, &format!("{}@fidonet", our)).await
+}
+
+impl Session {
+   // Our side calls the uplink
+   pub async fn call<S>(&self, stream: &mut S, outbound: Vec<FlowEntry>, inbound: &Path) -> Result<Stats>
+   where
+      S: AsyncRead + AsyncWrite + Unpin,
+   {
+      send_info(stream, self.our, None).await?;
+
+      // Remote addresses and the optional challenge
+      let mut challenge = None;
+      loop {
+         match read_frame(stream).await? {
+            Frame::Command(M_NUL, arg) => {
+               log::info!("binkp {}: {}", self.remote, arg);
+               if let Some(opt) = arg.strip_prefix("OPT ") {
+                  challenge = opt.split_whitespace()
+                  .find_map(|o| o.strip_prefix("CRAM-MD5-"))
+                  .map(String::from);
+               }
+            }
+            Frame::Command(M_ADR, arg) => {
+               if !parse_addrs(&arg).contains(&self.remote) {
+                  write_command(stream, M_ERR, "Wrong address").await?;
+                  return Err(Error::Ftn("binkp remote has other address"));
+               }
+               break;
+            }
+            Frame::Command(M_ERR, arg) | Frame::Command(M_BSY, arg) => {
+               log::info!("binkp {} refused: {}", self.remote, arg);
+               return Err(Error::Ftn("binkp session refused"));
+            }
+            _ => {}
+         }
+      }
+
+      // Plain password only if the remote does not support CRAM
+      let password = match (&challenge, self.password.is_empty()) {
+         (_, true) => String::from("-"),
+         (Some(challenge), false) => format!("CRAM-MD5-{}", cram_md5(&self.password, challenge)?),
+         (None, false) => self.password.clone(),
+      };
+      write_command(stream, M_PWD, &password).await?;
+
+      loop {
+         match read_frame(stream).await? {
+            Frame::Command(M_OK, _) => break,
+            Frame::Command(M_ERR, arg) | Frame::Command(M_BSY, arg) => {
+               log::info!("binkp {} refused: {}", self.remote, arg);
+               return Err(Error::Ftn("binkp password is not accepted"));
+            }
+            _ => {}
+         }
+      }
+
+      self.transfer(stream, outbound, inbound).await
+   }
+
+   // Files in both directions at the same time, the session ends after both M_EOB
+   async fn transfer<S>(&self, stream: &mut S, outbound: Vec<FlowEntry>, inbound: &Path) -> Result<Stats>
+   where
+      S: AsyncRead + AsyncWrite + Unpin,
+   {
+      let (mut reader, writer) = tokio::io::split(stream);
+      let writer = Mutex::new(writer);
+      let eob_sent = AtomicBool::new(false);
+
+      // Names of our files waiting for M_GOT
+      let pending = std::sync::Mutex::new(
+         outbound.iter()
+         .filter_map(|entry| Some((entry.path.file_name()?.to_str()?.to_string(), entry.clone())))
+         .collect::<Vec<_>>()
+      );
+
+      // Name, size, time, file, received bytes and the temporary name of the incoming file
+      let mut current: Option<(String, u64, String, File, u64, PathBuf)> = None;
+
+      let send = async {
+         for FlowEntry { path, .. } in &outbound {
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+               Some(name) => name,
+               None => continue,
+            };
+            let data = tokio::fs::read(path).await?;
+            let time = tokio::fs::metadata(path).await?
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+
+            write_command(&mut *writer.lock().await, M_FILE, &format!("{} {} {} 0", name, data.len(), time)).await?;
+            for block in data.chunks(BLOCK_LEN) {
+               write_data(&mut *writer.lock().await, block).await?;
+            }
+         }
+         write_command(&mut *writer.lock().await, M_EOB, "").await?;
+         eob_sent.store(true, Ordering::SeqCst);
+         Ok::<_, Error>(())
+      };
+
+      let receive = async {
+         let mut stats = Stats::default();
+         let mut remote_eob = false;
+
+         loop {
+            let done = remote_eob && current.is_none() && pending.lock().unwrap().is_empty() && eob_sent.load(Ordering::SeqCst);
+            let frame = match read_frame(&mut reader).await {
+               Ok(frame) => frame,
+               // The remote side closes the connection at the end
+               Err(_) if done => return Ok(stats),
+               Err(e) => return Err(e),
+            };
+
+            match frame {
+               Frame::Command(M_FILE, arg) => {
+                  let mut args = arg.split_whitespace();
+                  let (name, size, time) = match (args.next().and_then(safe_name), args.next().and_then(|s| s.parse().ok()), args.next()) {
+                     (Some(name), Some(size), Some(time)) => (name, size, String::from(time)),
+                     _ => return Err(Error::Ftn("binkp invalid M_FILE")),
+                  };
+                  // Other sessions may receive the file with the same name
+                  let tmp = inbound.join(format!("{:08x}.part", gateway::next_serial()));
+                  let file = File::create(&tmp).await?;
+                  current = Some((name, size, time, file, 0, tmp));
+               }
+               Frame::Data(data) => {
+                  if let Some((_, _, _, file, received, _)) = current.as_mut() {
+                     file.write_all(&data).await?;
+                     *received += data.len() as u64;
+                  }
+               }
+               Frame::Command(M_GOT, arg) => {
+                  if let Some(entry) = acknowledge(&pending, &arg) {
+                     bso::sent(&entry).await?;
+                     stats.sent += 1;
+                  }
+               }
+               // Skipped ones remain for the next session
+               Frame::Command(M_SKIP, arg) => {
+                  acknowledge(&pending, &arg);
+               }
+               Frame::Command(M_EOB, _) => remote_eob = true,
+               Frame::Command(M_GET, arg) => log::info!("binkp {} M_GET is not supported: {}", self.remote, arg),
+               Frame::Command(M_ERR, arg) => {
+                  log::info!("binkp {} error: {}", self.remote, arg);
+                  return Err(Error::Ftn("binkp remote error"));
+               }
+               Frame::Command(_, _) => {}
+            }
+
+            // File is complete
+            let complete = matches!(&current, Some((_, size, _, _, received, _)) if received >= size);
+            if complete {
+               if let Some((name, size, time, mut file, _, tmp)) = current.take() {
+                  file.flush().await?;
+                  drop(file);
+                  if let Err(e) = tokio::fs::rename(&tmp, free_name(inbound, &name)).await {
+                     let _ = tokio::fs::remove_file(&tmp).await;
+                     return Err(e.into());
+                  }
+                  write_command(&mut *writer.lock().await, M_GOT, &format!("{} {} {}", name, size, time)).await?;
+                  stats.received += 1;
+               }
+            }
+
+            let done = remote_eob && current.is_none() && pending.lock().unwrap().is_empty() && eob_sent.load(Ordering::SeqCst);
+            if done {
+               return Ok(stats);
+            }
+         }
+      };
+
+      let res = tokio::try_join!(send, receive);
+
+      // The partial file is useless, the remote side sends it again next time
+      if let Some((name, _, _, file, _, tmp)) = current.take() {
+         drop(file);
+         log::info!("binkp {} interrupted receiving {}", self.remote, name);
+         if let Err(e) = tokio::fs::remove_file(&tmp).await {
+            log::info!("binkp unable to remove {}: {}", tmp.display(), e);
+         }
+      }
+
+      let (_, stats) = res?;
+      writer.lock().await.shutdown().await?;
+      Ok(stats)
+   }
+}
+
+// The remote side calls us, only known links are accepted
+pub async fn answer<S>(stream: &mut S, our: Addr, links: &[Link], inbound: &Path) -> Result<(Addr, Stats)>
+where
+   S: AsyncRead + AsyncWrite + Unpin,
+{
+   let challenge = to_hex(&rand::random::<[u8; 16]>());
+   send_info(stream, our, Some(&challenge)).await?;
+
+   // Addresses of the remote side and then its password
+   let mut addrs = Vec::new();
+   let password = loop {
+      match read_frame(stream).await? {
+         Frame::Command(M_NUL, arg) => log::info!("binkp incoming: {}", arg),
+         Frame::Command(M_ADR, arg) => addrs = parse_addrs(&arg),
+         Frame::Command(M_PWD, arg) => break arg,
+         Frame::Command(M_ERR, arg) => {
+            log::info!("binkp incoming error: {}", arg);
+            return Err(Error::Ftn("binkp remote error"));
+         }
+         _ => {}
+      }
+   };
+
+   let (remote, expected) = match links.iter().find(|link| addrs.contains(&link.addr)) {
+      Some(link) => (&link.addr, &link.password),
+      None => {
+         write_command(stream, M_ERR, "Unknown address").await?;
+         return Err(Error::Ftn("binkp unknown remote address"));
+      }
+   };
+
+   // CRAM is preferred but the plain password is also accepted
+   let accepted = match password.strip_prefix("CRAM-MD5-") {
+      Some(digest) => secret::same(&cram_md5(expected, &challenge)?, &digest.to_lowercase()),
+      None => secret::same(expected, &password),
+   };
+   if !accepted {
+      write_command(stream, M_ERR, "Incorrect password").await?;
+      log::info!("binkp {} incorrect password", remote);
+      return Err(Error::Ftn("binkp incorrect password"));
+   }
+   write_command(stream, M_OK, "secure").await?;
+
+   // Only receiving, there is no outbound for links yet
+   let session = Session { our, remote: *remote, password: expected.clone() };
+   let stats = session.transfer(stream, Vec::new(), inbound).await?;
+   Ok((*remote, stats))
+}
+
+// Incoming sessions on FTN_BINKP_LISTEN
+pub async fn listen_loop() {
+   let (config, listen, inbound) = match gateway::config() {
+      Some(config) => match (&config.binkp_listen, &config.inbound) {
+         (Some(listen), Some(inbound)) => (config, listen, inbound),
+         _ => return,
+      },
+      None => return,
+   };
+
+   let listener = match TcpListener::bind(listen).await {
+      Ok(listener) => listener,
+      Err(e) => {
+         log::info!("binkp unable to listen on {}: {}", listen, e);
+         return;
+      }
+   };
+   log::info!("binkp listens on {} for {} links", listen, config.links.len());
+
+   let sessions = std::sync::Arc::new(Semaphore::new(MAX_SESSIONS));
+   loop {
+      let (mut stream, peer) = match listener.accept().await {
+         Ok(res) => res,
+         Err(e) => {
+            log::info!("binkp accept error: {}", e);
+            continue;
+         }
+      };
+
+      let permit = match sessions.clone().try_acquire_owned() {
+         Ok(permit) => permit,
+         Err(_) => {
+            log::info!("binkp too many sessions, {} is busy", peer);
+            let busy = write_command(&mut stream, M_BSY, "Too many sessions, try later");
+            let _ = tokio::time::timeout(Duration::from_secs(1), busy).await;
+            continue;
+         }
+      };
+
+      tokio::spawn(async move {
+         let _permit = permit;
+         match answer(&mut stream, config.addr, &config.links, inbound).await {
+            Ok((remote, stats)) => log::info!("binkp session with {} from {}: sent {}, received {}", remote, peer, stats.sent, stats.received),
+            Err(e) => log::info!("binkp session from {} failed: {}", peer, e),
+         }
+      });
+   }
+}
+
+// One session with the uplink
+pub async fn poll(config: &gateway::Config, uplink: Addr, (host, password): (&str, &str), inbound: &Path) -> Result<Stats> {
+   bso::pack(config).await?;
+   let outbound = bso::flow_entries(&config.outbound, config.addr.zone, uplink).await?;
+
+   let host = if host.contains(':') { host.to_string() } else { format!("{}:{}", host, DEFAULT_PORT) };
+   let mut stream = tokio::time::timeout(IDLE_TIMEOUT, TcpStream::connect(&host)).await
+   .map_err(|_| Error::Ftn("binkp connection timeout"))??;
+
+   let session = Session {
+      our: config.addr,
+      remote: uplink,
+      password: String::from(password),
+   };
+   session.call(&mut stream, outbound, inbound).await
+}
+
+// The uplink of the gateway and the ones of areas
+fn uplinks(config: &gateway::Config) -> Vec<Addr> {
+   let mut uplinks = vec![config.uplink];
+   for area in areas::all() {
+      if !uplinks.contains(&area.uplink) {
+         uplinks.push(area.uplink);
+      }
+   }
+   uplinks
+}
+
+// Periodic polling of FTN_BINKP_UPLINK and links with hosts
+pub async fn poll_loop() {
+   let (config, inbound) = match gateway::config() {
+      Some(config) if config.is_polling() => match &config.inbound {
+         Some(inbound) => (config, inbound),
+         None => return,
+      },
+      _ => return,
+   };
+
+   loop {
+      for uplink in uplinks(config) {
+         let host = match config.uplink_host(uplink) {
+            Some(host) => host,
+            None => {
+               log::info!("binkp does not know the host of {}, its mail stays in the outbound", uplink);
+               continue;
+            }
+         };
+         match poll(config, uplink, host, inbound).await {
+            Ok(stats) => log::info!("binkp session with {}: sent {}, received {}", uplink, stats.sent, stats.received),
+            Err(e) => log::info!("binkp session with {} failed: {}", uplink, e),
+         }
+      }
+      tokio::time::sleep(config.poll_interval).await;
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // Empty directory of the test
+   fn temp_dir(name: &str) -> PathBuf {
+      let dir = std::env::temp_dir().join(format!("n5011-binkp-{}-{}", name, std::process::id()));
+      let _ = std::fs::remove_dir_all(&dir);
+      std::fs::create_dir_all(&dir).unwrap();
+      dir
+   }
+
+   #[test]
+   fn cram_md5_of_rfc2104() {
+      let challenge = to_hex(b"what do ya want for nothing?");
+      assert_eq!(cram_md5("Jefe", &challenge).unwrap(), "750c783e6ab0b503eaa86e310a5db738");
+      assert!(cram_md5("secret", "xyz").is_err());
+   }
+
+   #[test]
+   fn names() {
+      assert_eq!(safe_name("0000fffe.mo0").as_deref(), Some("0000fffe.mo0"));
+      assert_eq!(safe_name("a\\x20b.pkt").as_deref(), Some("a b.pkt"));
+      assert_eq!(safe_name("../etc/passwd").as_deref(), None);
+      assert_eq!(safe_name("dir/file.pkt").as_deref(), Some("dir_file.pkt"));
+   }
+
+   #[tokio::test]
+   async fn call_and_answer() {
+      let dir = temp_dir("session");
+      let (outbound, inbound) = (dir.join("outbound"), dir.join("inbound"));
+      std::fs::create_dir_all(&outbound).unwrap();
+      std::fs::create_dir_all(&inbound).unwrap();
+
+      let point = Addr::new(2, 5011, 1, 5);
+      let boss = Addr::new(2, 5011, 1, 0);
+
+      // Bundle of several blocks and the line of the file that is not there yet
+      let bundle = outbound.join("0000fffe.mo0");
+      let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+      std::fs::write(&bundle, &data).unwrap();
+      let flow = bso::node_base(&outbound, point.zone, boss).with_extension("flo");
+      std::fs::write(&flow, format!("^{}\n^{}\n", bundle.display(), outbound.join("0000fffe.tu0").display())).unwrap();
+
+      // The same name is already in the inbound
+      std::fs::write(inbound.join("0000fffe.mo0"), b"old").unwrap();
+
+      let entries = bso::flow_entries(&outbound, point.zone, boss).await.unwrap();
+      assert_eq!(entries.len(), 1);
+
+      let session = Session { our: point, remote: boss, password: String::from("secret") };
+      let links = vec![Link { addr: point, password: String::from("secret"), host: None }];
+      let (mut a, mut b) = tokio::io::duplex(64 * 1024);
+      let (called, answered) = tokio::join!(
+         session.call(&mut a, entries, &outbound),
+         answer(&mut b, boss, &links, &inbound),
+      );
+
+      let called = called.unwrap();
+      let (remote, answered) = answered.unwrap();
+      assert_eq!((called.sent, called.received), (1, 0));
+      assert_eq!((remote, answered.sent, answered.received), (point, 0, 1));
+
+      // Received next to the old file, the sent bundle is deleted and its line is marked
+      assert_eq!(std::fs::read(inbound.join("0000fffe.mo0")).unwrap(), b"old");
+      assert_eq!(std::fs::read(inbound.join("0000fffe-1.mo0")).unwrap(), data);
+      assert!(!bundle.exists());
+      let lines = std::fs::read_to_string(&flow).unwrap();
+      assert_eq!(lines, format!("~^{}\n^{}\n", bundle.display(), outbound.join("0000fffe.tu0").display()));
+
+      std::fs::remove_dir_all(&dir).unwrap();
+   }
+
+   #[tokio::test]
+   async fn interrupted_file() {
+      let inbound = temp_dir("interrupted");
+      let session = Session { our: Addr::new(2, 5011, 1, 0), remote: Addr::new(2, 5011, 1, 5), password: String::new() };
+      let (mut a, mut b) = tokio::io::duplex(64 * 1024);
+
+      // The remote side drops the connection in the middle of the file
+      let remote = async {
+         write_command(&mut a, M_FILE, "0000fffe.mo0 1000 0 0").await.unwrap();
+         write_data(&mut a, &[0; 100]).await.unwrap();
+         while !has_part(&inbound) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+         }
+         drop(a);
+      };
+      let (received, _) = tokio::join!(session.transfer(&mut b, Vec::new(), &inbound), remote);
+
+      assert!(received.is_err());
+      assert_eq!(std::fs::read_dir(&inbound).unwrap().count(), 0);
+      std::fs::remove_dir_all(&inbound).unwrap();
+   }
+
+   fn has_part(dir: &Path) -> bool {
+      std::fs::read_dir(dir).unwrap().any(|entry| entry.unwrap().path().extension().is_some_and(|ext| ext == "part"))
+   }
+
+   #[tokio::test]
+   async fn wrong_password() {
+      let dir = temp_dir("password");
+      let point = Addr::new(2, 5011, 1, 5);
+      let boss = Addr::new(2, 5011, 1, 0);
+
+      let session = Session { our: point, remote: boss, password: String::from("wrong") };
+      let links = vec![Link { addr: point, password: String::from("secret"), host: None }];
+      let (mut a, mut b) = tokio::io::duplex(64 * 1024);
+      let (called, answered) = tokio::join!(
+         session.call(&mut a, Vec::new(), &dir),
+         answer(&mut b, boss, &links, &dir),
+      );
+      assert!(called.is_err());
+      assert!(answered.is_err());
+
+      std::fs::remove_dir_all(&dir).unwrap();
+   }
+}
@@ -0,0 +1,292 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Binkley style outbound and arcmail bundles, FTS-5005. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}, time::Duration};
+use chrono::{Datelike, Utc};
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{Addr, gateway, pkt::Packet};
+use crate::error::{Error, Result};
+
+// Bundles are named after the day of the week
+const DAYS: [&str; 7] = ["mo", "tu", "we", "th", "fr", "sa", "su"];
+
+// How often loose packets go to the bundle
+const PACK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Packing from the loop and before the session, and marking of sent files in flow files
+static PACKING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// What to do with the file after sending
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+   Keep,
+   Delete,
+   Truncate,
+}
+
+// Line of the flow file
+#[derive(Clone, Debug)]
+pub struct FlowEntry {
+   pub path: PathBuf,
+   pub action: Action,
+   pub flow: PathBuf,
+}
+
+// Base name of files for the node like "139300fa", for points "139300fa.pnt/00000005",
+// other zones are in "outbound.002"
+pub fn node_base(outbound: &Path, our_zone: u16, addr: Addr) -> PathBuf {
+   let dir = if addr.zone == our_zone {
+      outbound.to_path_buf()
+   } else {
+      let name = outbound.file_name().and_then(|n| n.to_str()).unwrap_or("outbound");
+      outbound.with_file_name(format!("{}.{:03x}", name, addr.zone))
+   };
+
+   let node = format!("{:04x}{:04x}", addr.net, addr.node);
+   if addr.point == 0 {
+      dir.join(node)
+   } else {
+      dir.join(format!("{}.pnt", node)).join(format!("{:08x}", addr.point))
+   }
+}
+
+// Name of the bundle without extension from the difference of addresses
+fn bundle_base(our: Addr, dest: Addr) -> String {
+   if our.net == dest.net && our.node == dest.node {
+      format!("0000{:04x}", our.point.wrapping_sub(dest.point))
+   } else {
+      format!("{:04x}{:04x}", our.net.wrapping_sub(dest.net), our.node.wrapping_sub(dest.node))
+   }
+}
+
+// Extension like "su0" with the number of the bundle of the day
+pub fn is_bundle(path: &Path) -> bool {
+   let ext = match path.extension().and_then(|ext| ext.to_str()) {
+      Some(ext) if ext.len() == 3 => ext.to_lowercase(),
+      _ => return false,
+   };
+   DAYS.contains(&&ext[..2]) && ext[2..].chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Free name of today's bundle
+fn bundle_name(dir: &Path, base: &str) -> Result<PathBuf> {
+   let day = DAYS[Utc::now().weekday().num_days_from_monday() as usize];
+   ('0'..='9').chain('a'..='z')
+   .map(|n| dir.join(format!("{}.{}{}", base, day, n)))
+   .find(|path| !path.exists())
+   .ok_or(Error::Ftn("no free name for the bundle"))
+}
+
+fn is_pkt(path: &Path) -> bool {
+   path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pkt"))
+}
+
+async fn list(dir: &Path, filter: fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+   let mut files = Vec::new();
+   let mut entries = tokio::fs::read_dir(dir).await?;
+   while let Some(entry) = entries.next_entry().await? {
+      let path = entry.path();
+      if filter(&path) {
+         files.push(path);
+      }
+   }
+   files.sort();
+   Ok(files)
+}
+
+fn zip_packets(bundle: &Path, packets: &[PathBuf]) -> zip::result::ZipResult<()> {
+   let tmp = bundle.with_extension("tmp");
+   let mut zip = ZipWriter::new(fs::File::create(&tmp)?);
+   let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+   for path in packets {
+      let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+      zip.start_file(name, options)?;
+      io::copy(&mut fs::File::open(path)?, &mut zip)?;
+   }
+   zip.finish()?;
+   fs::rename(&tmp, bundle)?;
+   Ok(())
+}
+
+// Loose packets of the outbound go to bundles for their destinations
+pub async fn pack(config: &gateway::Config) -> Result<usize> {
+   let _lock = PACKING.lock().await;
+
+   // Areas may have different uplinks
+   let mut by_dest: BTreeMap<Addr, Vec<PathBuf>> = BTreeMap::new();
+   for path in list(&config.outbound, is_pkt).await? {
+      let dest = Packet::parse(&tokio::fs::read(&path).await?).map_or(config.uplink, |packet| packet.dest);
+      by_dest.entry(dest).or_default().push(path);
+   }
+
+   let mut count = 0;
+   for (dest, packets) in by_dest {
+      let base = node_base(&config.outbound, config.addr.zone, dest);
+      let dir = base.parent().map(Path::to_path_buf).unwrap_or_default();
+      tokio::fs::create_dir_all(&dir).await?;
+      let bundle = bundle_name(&dir, &bundle_base(config.addr, dest))?;
+
+      let (path, list) = (bundle.clone(), packets.clone());
+      tokio::task::spawn_blocking(move || zip_packets(&path, &list)).await
+      .map_err(|_| Error::Ftn("packing task failed"))?
+      .map_err(|_| Error::Ftn("unable to write the bundle"))?;
+
+      // The bundle is deleted after sending
+      let flow = base.with_extension("flo");
+      let mut lines = tokio::fs::read_to_string(&flow).await.unwrap_or_default();
+      lines.push_str(&format!("^{}\n", bundle.display()));
+      write_flow(&flow, &lines).await?;
+
+      for path in &packets {
+         tokio::fs::remove_file(path).await?;
+      }
+      log::info!("Packed {} packets for {} to {}", packets.len(), dest, bundle.display());
+      count += packets.len();
+   }
+   Ok(count)
+}
+
+// The flow file is replaced at once, the mailer never reads a half-written one
+async fn write_flow(flow: &Path, text: &str) -> Result<()> {
+   let mut tmp = flow.as_os_str().to_owned();
+   tmp.push(".tmp");
+   tokio::fs::write(&tmp, text).await?;
+   tokio::fs::rename(&tmp, flow).await?;
+   Ok(())
+}
+
+// Periodic packing of the outbound
+pub async fn pack_loop() {
+   let config = match gateway::config() {
+      Some(config) => config,
+      None => return,
+   };
+
+   loop {
+      if let Err(e) = pack(config).await {
+         log::info!("Error packing {}: {}", config.outbound.display(), e);
+      }
+      tokio::time::sleep(PACK_INTERVAL).await;
+   }
+}
+
+// Files to send to the node, crash ones first
+pub async fn flow_entries(outbound: &Path, our_zone: u16, addr: Addr) -> Result<Vec<FlowEntry>> {
+   let base = node_base(outbound, our_zone, addr);
+   let mut entries = Vec::new();
+
+   for flow in [base.with_extension("clo"), base.with_extension("flo")] {
+      let text = match tokio::fs::read_to_string(&flow).await {
+         Ok(text) => text,
+         Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+         Err(e) => return Err(e.into()),
+      };
+
+      for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+         let (action, name) = match line.as_bytes()[0] {
+            // Already sent and comments
+            b'~' | b';' => continue,
+            b'^' | b'-' => (Action::Delete, &line[1..]),
+            b'#' => (Action::Truncate, &line[1..]),
+            b'@' => (Action::Keep, &line[1..]),
+            _ => (Action::Keep, line),
+         };
+         let path = outbound.join(name);
+         if path.is_file() {
+            entries.push(FlowEntry { path, action, flow: flow.clone() });
+         }
+      }
+   }
+   Ok(entries)
+}
+
+// The file is received by the remote side, the line of the flow file is marked as sent
+pub async fn sent(entry: &FlowEntry) -> Result<()> {
+   match entry.action {
+      Action::Delete => tokio::fs::remove_file(&entry.path).await?,
+      Action::Truncate => tokio::fs::write(&entry.path, b"").await?,
+      Action::Keep => {}
+   }
+
+   // Packing may add a bundle to the same flow file during the session
+   let _lock = PACKING.lock().await;
+   let text = tokio::fs::read_to_string(&entry.flow).await?;
+   let mut marked = false;
+   let lines: Vec<String> = text.lines()
+   .map(|line| {
+      let name = line.trim().trim_start_matches(['^', '-', '#', '@']);
+      if !marked && !line.starts_with('~') && !name.is_empty() && Path::new(name).ends_with(entry.path.file_name().unwrap_or_default()) {
+         marked = true;
+         format!("~{}", line.trim())
+      } else {
+         String::from(line)
+      }
+   })
+   .collect();
+
+   // Nothing left to send
+   if lines.iter().all(|line| line.trim().is_empty() || line.starts_with('~')) {
+      tokio::fs::remove_file(&entry.flow).await?;
+   } else {
+      write_flow(&entry.flow, &(lines.join("\n") + "\n")).await?;
+   }
+   Ok(())
+}
+
+fn unzip_packets(bundle: &Path, dir: &Path) -> zip::result::ZipResult<usize> {
+   let mut zip = ZipArchive::new(fs::File::open(bundle)?)?;
+   let mut count = 0;
+   for i in 0..zip.len() {
+      let mut file = zip.by_index(i)?;
+      let is_packet = file.enclosed_name().is_some_and(is_pkt);
+      if !is_packet {
+         continue;
+      }
+
+      // Unique names keep the order of bundles, an existing packet is never replaced
+      let name = dir.join(format!("{:08x}.pkt", gateway::next_serial()));
+      let tmp = name.with_extension("tmp");
+      io::copy(&mut file, &mut fs::File::create(&tmp)?)?;
+      let res = fs::hard_link(&tmp, &name);
+      fs::remove_file(&tmp)?;
+      res?;
+      count += 1;
+   }
+   Ok(count)
+}
+
+// Inbound bundles become packets, broken ones are renamed to .bad
+pub async fn unpack(dir: &Path) -> Result<()> {
+   for bundle in list(dir, is_bundle).await? {
+      // Truncated bundle is already processed by the sender
+      if tokio::fs::metadata(&bundle).await?.len() == 0 {
+         tokio::fs::remove_file(&bundle).await?;
+         continue;
+      }
+
+      let (path, target) = (bundle.clone(), dir.to_path_buf());
+      let res = tokio::task::spawn_blocking(move || unzip_packets(&path, &target)).await
+      .map_err(|_| Error::Ftn("unpacking task failed"))?;
+
+      match res {
+         Ok(count) => {
+            log::info!("Unpacked {} packets from {}", count, bundle.display());
+            tokio::fs::remove_file(&bundle).await?;
+         }
+         Err(e) => {
+            log::info!("Bad bundle {}: {}", bundle.display(), e);
+            tokio::fs::rename(&bundle, bundle.with_extension("bad")).await?;
+         }
+      }
+   }
+   Ok(())
+}
@@ -0,0 +1,192 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Charsets of FidoNet messages, FTS-5003. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::str::FromStr;
+use encoding_rs::{Encoding, IBM866, KOI8_R, WINDOWS_1251, UTF_8};
+
+use crate::error::{Error, Result};
+use crate::metrics;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+   Cp866,
+   Koi8r,
+   Cp1251,
+   Utf8,
+}
+
+impl FromStr for Charset {
+   type Err = Error;
+
+   fn from_str(s: &str) -> Result<Self> {
+      Self::from_chrs(s).ok_or(Error::Settings("unknown charset, expected cp866, koi8-r, cp1251 or utf-8"))
+   }
+}
+
+impl Charset {
+   // Value of the CHRS kludge like "CP866 2", in Russian echoes IBMPC also means CP866
+   pub fn from_chrs(value: &str) -> Option<Self> {
+      let name = value.split_whitespace().next()?.to_uppercase();
+      match name.as_str() {
+         "CP866" | "IBMPC" | "+7_FIDO" | "+7" => Some(Charset::Cp866),
+         "KOI8-R" | "KOI8" => Some(Charset::Koi8r),
+         "CP1251" | "WINDOWS-1251" => Some(Charset::Cp1251),
+         "UTF-8" | "UTF8" => Some(Charset::Utf8),
+         _ => None,
+      }
+   }
+
+   // Value for the CHRS kludge with the level
+   pub fn chrs(&self) -> &'static str {
+      match self {
+         Charset::Cp866 => "CP866 2",
+         Charset::Koi8r => "KOI8-R 2",
+         Charset::Cp1251 => "CP1251 2",
+         Charset::Utf8 => "UTF-8 4",
+      }
+   }
+
+   fn encoding(&self) -> &'static Encoding {
+      match self {
+         Charset::Cp866 => IBM866,
+         Charset::Koi8r => KOI8_R,
+         Charset::Cp1251 => WINDOWS_1251,
+         Charset::Utf8 => UTF_8,
+      }
+   }
+
+   // Text and whether something was replaced
+   pub fn decode(&self, bytes: &[u8]) -> (String, bool) {
+      let (text, lossy) = self.encoding().decode_without_bom_handling(bytes);
+      if lossy {
+         metrics::CHARSET_SUBSTITUTIONS.inc();
+      }
+      (text.into_owned(), lossy)
+   }
+
+   // Bytes and whether something was replaced, typographic characters become ASCII
+   pub fn encode(&self, text: &str) -> (Vec<u8>, bool) {
+      if *self == Charset::Utf8 {
+         return (text.as_bytes().to_vec(), false);
+      }
+
+      let encoding = self.encoding();
+      let mut bytes = Vec::with_capacity(text.len());
+      let mut lossy = false;
+      let mut buf = [0u8; 4];
+
+      for c in text.chars() {
+         let (encoded, _, failed) = encoding.encode(c.encode_utf8(&mut buf));
+         if !failed {
+            bytes.extend_from_slice(&encoded);
+         } else if let Some(replacement) = replacement(c) {
+            bytes.extend_from_slice(replacement.as_bytes());
+         } else {
+            bytes.push(b'?');
+            lossy = true;
+         }
+      }
+
+      if lossy {
+         metrics::CHARSET_SUBSTITUTIONS.inc();
+      }
+      (bytes, lossy)
+   }
+}
+
+// Charset from the CHRS kludge of the message text
+pub fn detect(text: &[u8]) -> Option<Charset> {
+   const CHRS: &[u8] = b"\x01CHRS:";
+
+   let start = text.windows(CHRS.len()).position(|w| w == CHRS)? + CHRS.len();
+   let value: Vec<u8> = text[start..].iter().copied().take_while(|b| *b != b'\r' && *b != b'\n').collect();
+   Charset::from_chrs(&String::from_utf8_lossy(&value))
+}
+
+// Valid UTF-8 as is, otherwise the default charset
+pub fn decode_auto(bytes: &[u8], default: Charset) -> (String, bool) {
+   match std::str::from_utf8(bytes) {
+      Ok(text) => (String::from(text), false),
+      Err(_) => default.decode(bytes),
+   }
+}
+
+// ASCII for characters of Telegram absent in the old code pages
+fn replacement(c: char) -> Option<&'static str> {
+   match c {
+      '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{2032}' => Some("'"),
+      '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{00ab}' | '\u{00bb}' | '\u{2033}' => Some("\""),
+      '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2015}' | '\u{2212}' => Some("-"),
+      '\u{2026}' => Some("..."),
+      '\u{00a0}' | '\u{2002}' | '\u{2003}' | '\u{2009}' | '\u{202f}' => Some(" "),
+      '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{fe0f}' => Some(""),
+      '\u{2022}' => Some("*"),
+      '\u{00a9}' => Some("(c)"),
+      '\u{00ae}' => Some("(r)"),
+      '\u{2122}' => Some("(tm)"),
+      '\u{2116}' => Some("N"),
+      _ => None,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const TEXT: &str = "Привет, FidoNet! Ёлка";
+
+   #[test]
+   fn round_trips() {
+      for charset in [Charset::Cp866, Charset::Koi8r, Charset::Cp1251, Charset::Utf8] {
+         let (bytes, lossy) = charset.encode(TEXT);
+         assert!(!lossy);
+         assert_eq!(charset.decode(&bytes), (String::from(TEXT), false));
+      }
+
+      // Known bytes of "Пр"
+      assert_eq!(Charset::Cp866.encode("Пр").0, vec![0x8f, 0xe0]);
+      assert_eq!(Charset::Koi8r.encode("Пр").0, vec![0xf0, 0xd2]);
+      assert_eq!(Charset::Utf8.encode("Пр").0, "Пр".as_bytes());
+   }
+
+   #[test]
+   fn chrs() {
+      assert_eq!(Charset::from_chrs("IBMPC 2"), Some(Charset::Cp866));
+      assert_eq!(Charset::from_chrs("koi8-r 2"), Some(Charset::Koi8r));
+      assert_eq!(Charset::from_chrs("LATIN-1 2"), None);
+      for charset in [Charset::Cp866, Charset::Koi8r, Charset::Cp1251, Charset::Utf8] {
+         assert_eq!(Charset::from_chrs(charset.chrs()), Some(charset));
+      }
+
+      assert_eq!(detect(b"AREA:N5020.BOT\r\x01CHRS: KOI8-R 2\rtext"), Some(Charset::Koi8r));
+      assert_eq!(detect(b"\x01CHRS: UTF-8 4"), Some(Charset::Utf8));
+      assert_eq!(detect(b"no kludges\r"), None);
+   }
+
+   #[test]
+   fn decode_auto_utf8() {
+      assert_eq!(decode_auto(TEXT.as_bytes(), Charset::Cp866), (String::from(TEXT), false));
+      let (bytes, _) = Charset::Cp866.encode(TEXT);
+      assert_eq!(decode_auto(&bytes, Charset::Cp866), (String::from(TEXT), false));
+   }
+
+   #[test]
+   fn substitutions() {
+      // Typographic characters become ASCII without a loss
+      assert_eq!(Charset::Cp866.encode("«Да» — это…"), (b"\"\x84\xa0\" - \xed\xe2\xae...".to_vec(), false));
+
+      // Others are counted, tests run in parallel so the counter only grows
+      let before = metrics::CHARSET_SUBSTITUTIONS.get();
+      assert_eq!(Charset::Koi8r.encode("a😀b"), (b"a?b".to_vec(), true));
+      let (text, lossy) = Charset::Utf8.decode(b"a\xffb");
+      assert!(lossy);
+      assert_eq!(text, "a\u{fffd}b");
+      assert!(metrics::CHARSET_SUBSTITUTIONS.get() >= before + 2);
+   }
+}
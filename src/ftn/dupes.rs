@@ -0,0 +1,94 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Dupe checking of echomail. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::time::Duration;
+use chrono::Utc;
+use md5::{Digest, Md5};
+
+use super::gateway;
+use crate::database as db;
+use crate::metrics;
+
+// How often old records are removed
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Direction of the message for metrics
+#[derive(Clone, Copy)]
+pub enum Direction {
+   Import,
+   Export,
+}
+
+impl Direction {
+   fn label(&self) -> &'static str {
+      match self {
+         Direction::Import => "import",
+         Direction::Export => "export",
+      }
+   }
+}
+
+// Hex MD5 of the parts
+pub fn hash(parts: &[&str]) -> String {
+   let mut md5 = Md5::new();
+   for part in parts {
+      md5.update(part.as_bytes());
+      md5.update([0]);
+   }
+   md5.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Known MSGID or content in the area, without the database nothing is a dupe
+pub async fn is_dupe(area: &str, msgid: &str, hash: &str, direction: Direction) -> bool {
+   if !db::is_available() {
+      return false;
+   }
+
+   match db::find_dupe(area, msgid, hash).await {
+      Ok(true) => {
+         metrics::DUPES.with_label_values(&[direction.label()]).inc();
+         log::info!("Dupe {} in {} on {}", msgid, area, direction.label());
+         true
+      }
+      Ok(false) => false,
+      Err(e) => {
+         log::info!("Error find_dupe() for {}: {}", msgid, e);
+         false
+      }
+   }
+}
+
+pub async fn remember(area: &str, msgid: &str, hash: &str) {
+   if !db::is_available() {
+      return;
+   }
+   if let Err(e) = db::save_dupe(area, msgid, hash, Utc::now()).await {
+      log::info!("Error save_dupe() for {}: {}", msgid, e);
+   }
+}
+
+// Periodic removal of records older than FTN_DUPE_DAYS
+pub async fn cleanup_loop() {
+   let config = match gateway::config() {
+      Some(config) => config,
+      None => return,
+   };
+
+   loop {
+      if db::is_available() {
+         let before = Utc::now() - chrono::Duration::days(config.dupe_days);
+         match db::delete_old_dupes(before).await {
+            Ok(0) => {}
+            Ok(count) => log::info!("Removed {} old dupe records", count),
+            Err(e) => log::info!("Error delete_old_dupes(): {}", e),
+         }
+      }
+      tokio::time::sleep(CLEANUP_INTERVAL).await;
+   }
+}
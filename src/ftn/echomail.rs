@@ -0,0 +1,295 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Echomail messages, FTS-0004 and FTS-0009. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use super::Addr;
+
+// Longest line of origin, SEEN-BY and PATH
+const ORIGIN_LEN: usize = 79;
+const CONTROL_LEN: usize = 79;
+
+pub struct Echomail {
+   pub area: String,
+   pub from: String,
+   pub to: String,
+   pub subject: String,
+   pub msgid: String,
+   pub reply: Option<String>,
+   pub body: String,
+   pub tearline: String,
+   pub origin: String,
+   pub origin_addr: Addr,
+   pub seen_by: Vec<Addr>,
+   pub path: Vec<Addr>,
+}
+
+impl Echomail {
+   // Message from the packet, None for netmail without AREA
+   pub fn parse(from: &str, to: &str, subject: &str, text: &str, orig: Addr) -> Option<Self> {
+      let mut lines = text.split('\r').map(|line| line.trim_start_matches('\n'));
+      let area = lines.next()?.strip_prefix("AREA:")?.trim().to_uppercase();
+
+      let mut mail = Self {
+         area,
+         from: String::from(from),
+         to: String::from(to),
+         subject: String::from(subject),
+         msgid: String::new(),
+         reply: None,
+         body: String::new(),
+         tearline: String::new(),
+         origin: String::new(),
+         origin_addr: orig,
+         seen_by: Vec::new(),
+         path: Vec::new(),
+      };
+
+      // Kludges and control lines are anywhere, the text is before the origin
+      let mut body = Vec::new();
+      for line in lines {
+         if let Some(kludge) = line.strip_prefix('\x01') {
+            if let Some(msgid) = kludge.strip_prefix("MSGID:") {
+               mail.msgid = String::from(msgid.trim());
+            } else if let Some(reply) = kludge.strip_prefix("REPLY:") {
+               mail.reply = Some(String::from(reply.trim()));
+            } else if let Some(path) = kludge.strip_prefix("PATH:") {
+               mail.path.extend(parse_net_nodes(path, orig.zone, mail.path.last().copied()));
+            }
+         } else if let Some(seen_by) = line.strip_prefix("SEEN-BY:") {
+            mail.seen_by.extend(parse_net_nodes(seen_by, orig.zone, mail.seen_by.last().copied()));
+         } else if let Some(origin) = line.strip_prefix(" * Origin:") {
+            mail.origin = String::from(origin.trim());
+            // Text before the origin only
+            if body.last().is_some_and(|last: &&str| last.starts_with("---")) {
+               mail.tearline = String::from(body.pop().unwrap_or_default().trim_start_matches('-').trim());
+            }
+            mail.body = body.join("\n").trim_end().to_string();
+            body.clear();
+         } else {
+            body.push(line);
+         }
+      }
+
+      // Without origin everything is the text
+      if mail.origin.is_empty() {
+         mail.body = body.join("\n").trim_end().to_string();
+      }
+
+      // Author address from the origin or MSGID, otherwise the sender of the packet
+      if let Some((origin, addr)) = split_origin(&mail.origin) {
+         mail.origin = origin;
+         mail.origin_addr = addr;
+      } else if let Some(addr) = Addr::find(&mail.msgid) {
+         mail.origin_addr = addr;
+      }
+
+      Some(mail)
+   }
+
+   // SEEN-BY is the set of 2D addresses sorted by net and node
+   pub fn add_seen_by(&mut self, addrs: &[Addr]) {
+      self.seen_by.extend(addrs.iter().map(|a| Addr::new(0, a.net, a.node, 0)));
+      self.seen_by.sort_by_key(|a| (a.net, a.node));
+      self.seen_by.dedup_by_key(|a| (a.net, a.node));
+   }
+
+   // PATH is in the order of systems, the last one is not repeated
+   pub fn add_path(&mut self, addr: Addr) {
+      let addr = Addr::new(0, addr.net, addr.node, 0);
+      if self.path.last().map(|a| (a.net, a.node)) != Some((addr.net, addr.node)) {
+         self.path.push(addr);
+      }
+   }
+
+   // The message has already passed through the system
+   pub fn has_path(&self, addr: Addr) -> bool {
+      self.path.iter().any(|a| a.net == addr.net && a.node == addr.node)
+   }
+
+   // Text of the packed message with CR as the line separator
+   pub fn text(&self, chrs: &str) -> String {
+      let mut lines = vec![format!("AREA:{}", self.area)];
+      lines.push(format!("\x01MSGID: {}", self.msgid));
+      if let Some(reply) = &self.reply {
+         lines.push(format!("\x01REPLY: {}", reply));
+      }
+      lines.push(format!("\x01CHRS: {}", chrs));
+      lines.push(String::from("\x01TZUTC: 0000"));
+
+      lines.extend(self.body.lines().map(String::from));
+
+      lines.push(format!("--- {}", self.tearline));
+      lines.push(origin_line(&self.origin, self.origin_addr));
+      lines.extend(control_lines("SEEN-BY:", &self.seen_by));
+      lines.extend(control_lines("\x01PATH:", &self.path));
+
+      let mut text = lines.join("\r");
+      text.push('\r');
+      text
+   }
+}
+
+// Origin clipped to the line length, the address stays intact
+fn origin_line(origin: &str, addr: Addr) -> String {
+   let addr = format!(" ({})", addr);
+   let prefix = " * Origin: ";
+   let room = ORIGIN_LEN.saturating_sub(prefix.len() + addr.len());
+   let origin: String = origin.chars().take(room).collect();
+   format!("{}{}{}", prefix, origin.trim_end(), addr)
+}
+
+// 2D addresses like "SEEN-BY: 5011/1 2 3 5020/100", the net is omitted if it is the same,
+// every line starts with the full address
+fn control_lines(prefix: &str, addrs: &[Addr]) -> Vec<String> {
+   let mut lines = Vec::new();
+   let mut line = String::from(prefix);
+   let mut net = None;
+
+   for a in addrs {
+      let short = format!(" {}", a.node);
+      let full = format!(" {}/{}", a.net, a.node);
+      let item = if net == Some(a.net) { short } else { full.clone() };
+
+      if line.len() + item.len() > CONTROL_LEN && line.len() > prefix.len() {
+         lines.push(line);
+         line = format!("{}{}", prefix, full);
+      } else {
+         line.push_str(&item);
+      }
+      net = Some(a.net);
+   }
+
+   if line.len() > prefix.len() {
+      lines.push(line);
+   }
+   lines
+}
+
+// Text and the address in the last brackets of the origin
+fn split_origin(origin: &str) -> Option<(String, Addr)> {
+   let start = origin.rfind('(')?;
+   let end = origin[start..].find(')')? + start;
+   let addr = origin[start + 1..end].parse().ok()?;
+   Some((String::from(origin[..start].trim_end()), addr))
+}
+
+// "5011/1 2 3 5020/100" where a node without net belongs to the previous net
+fn parse_net_nodes(s: &str, zone: u16, last: Option<Addr>) -> Vec<Addr> {
+   let mut net = last.map(|a| a.net);
+   s.split_whitespace()
+   .filter_map(|token| {
+      let (n, node) = match token.split_once('/') {
+         Some((n, node)) => (n.parse().ok()?, node),
+         None => (net?, token),
+      };
+      net = Some(n);
+      let node = node.split('.').next()?.parse().ok()?;
+      Some(Addr::new(zone, n, node, 0))
+   })
+   .collect()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn nodes(addrs: &[Addr]) -> Vec<(u16, u16)> {
+      addrs.iter().map(|a| (a.net, a.node)).collect()
+   }
+
+   fn sample() -> Echomail {
+      let text = "AREA:N5020.BOT\r\x01MSGID: 2:5011/1 0123abcd\rHello\r--- GoldED+\r * Origin: Station (2:5011/1)\r";
+      Echomail::parse("Sysop", "All", "Test", text, Addr::new(2, 5011, 1, 0)).unwrap()
+   }
+
+   #[test]
+   fn seen_by_sorted_and_unique() {
+      let mut mail = sample();
+      mail.add_seen_by(&[Addr::new(2, 5020, 100, 0), Addr::new(2, 5011, 2, 0), Addr::new(2, 5011, 1, 5), Addr::new(2, 5020, 100, 0)]);
+      assert_eq!(nodes(&mail.seen_by), vec![(5011, 1), (5011, 2), (5020, 100)]);
+      assert_eq!(control_lines("SEEN-BY:", &mail.seen_by), vec!["SEEN-BY: 5011/1 2 5020/100"]);
+   }
+
+   #[test]
+   fn wrapped_lines() {
+      let addrs: Vec<Addr> = (100..=130).map(|node| Addr::new(2, 5011, node, 0)).collect();
+      let lines = control_lines("SEEN-BY:", &addrs);
+
+      let first: Vec<String> = (101..=115).map(|n| n.to_string()).collect();
+      let second: Vec<String> = (117..=130).map(|n| n.to_string()).collect();
+      assert_eq!(lines, vec![
+         format!("SEEN-BY: 5011/100 {}", first.join(" ")),
+         format!("SEEN-BY: 5011/116 {}", second.join(" ")),
+      ]);
+      assert!(lines.iter().all(|line| line.len() <= CONTROL_LEN));
+   }
+
+   #[test]
+   fn full_address_at_line_start() {
+      let addrs: Vec<Addr> = (1..=20).flat_map(|node| [Addr::new(2, 5011, node, 0), Addr::new(2, 5020 + node, 1, 0)]).collect();
+      let lines = control_lines("\x01PATH:", &addrs);
+      assert!(lines.len() > 1);
+      for line in &lines {
+         assert!(line.len() <= CONTROL_LEN);
+         let first = line.trim_start_matches("\x01PATH:").split_whitespace().next().unwrap();
+         assert!(first.contains('/'), "{}", line);
+      }
+
+      // Back from the lines in the same order
+      let mut parsed = Vec::new();
+      for line in &lines {
+         let last = parsed.last().copied();
+         parsed.extend(parse_net_nodes(line.trim_start_matches("\x01PATH:"), 2, last));
+      }
+      assert_eq!(nodes(&parsed), nodes(&addrs));
+   }
+
+   #[test]
+   fn parse_continued_lines() {
+      let text = "AREA:N5020.BOT\rHello\r * Origin: Station (2:5011/1)\r\
+         SEEN-BY: 5011/1 2\rSEEN-BY: 3 5020/100\r\x01PATH: 5020/100\r\x01PATH: 200 5011/1.5\r";
+      let mail = Echomail::parse("Sysop", "All", "Test", text, Addr::new(2, 5020, 100, 0)).unwrap();
+      assert_eq!(nodes(&mail.seen_by), vec![(5011, 1), (5011, 2), (5011, 3), (5020, 100)]);
+      assert_eq!(nodes(&mail.path), vec![(5020, 100), (5020, 200), (5011, 1)]);
+      assert_eq!(mail.body, "Hello");
+      assert_eq!(mail.origin_addr, Addr::new(2, 5011, 1, 0));
+   }
+
+   #[test]
+   fn path() {
+      let mut mail = sample();
+      mail.add_path(Addr::new(2, 5011, 1, 0));
+      mail.add_path(Addr::new(2, 5011, 1, 5));
+      mail.add_path(Addr::new(2, 5020, 100, 0));
+      assert_eq!(nodes(&mail.path), vec![(5011, 1), (5020, 100)]);
+      assert!(mail.has_path(Addr::new(2, 5011, 1, 5)));
+      assert!(!mail.has_path(Addr::new(2, 5011, 2, 0)));
+      assert_eq!(control_lines("\x01PATH:", &mail.path), vec!["\x01PATH: 5011/1 5020/100"]);
+   }
+
+   #[test]
+   fn text_round_trip() {
+      let mut mail = sample();
+      mail.origin = "x".repeat(100);
+      mail.add_seen_by(&[Addr::new(2, 5011, 1, 0), Addr::new(2, 5020, 100, 0)]);
+      mail.add_path(Addr::new(2, 5011, 1, 0));
+
+      let text = mail.text("CP866 2");
+      let origin = text.split('\r').find(|line| line.starts_with(" * Origin:")).unwrap();
+      assert_eq!(origin.len(), ORIGIN_LEN);
+      assert!(origin.ends_with(" (2:5011/1)"));
+
+      let parsed = Echomail::parse("Sysop", "All", "Test", &text, Addr::new(2, 5011, 1, 0)).unwrap();
+      assert_eq!(parsed.area, "N5020.BOT");
+      assert_eq!(parsed.msgid, "2:5011/1 0123abcd");
+      assert_eq!(parsed.body, "Hello");
+      assert_eq!(parsed.tearline, "GoldED+");
+      assert_eq!(nodes(&parsed.seen_by), vec![(5011, 1), (5020, 100)]);
+      assert_eq!(nodes(&parsed.path), vec![(5011, 1)]);
+   }
+}
@@ -0,0 +1,742 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Gateway between Telegram and FidoNet. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{collections::HashMap, convert::TryFrom, env, path::{Path, PathBuf}, sync::{Mutex, atomic::{AtomicU32, Ordering}}, time::Duration};
+use once_cell::sync::{Lazy, OnceCell};
+use chrono::{DateTime, Utc};
+use teloxide::{prelude::*, types::User};
+
+use super::{Addr, areas::{self, Area}, bso, dupes::{self, Direction}, quote, charset::{self, Charset}, echomail::Echomail, netmail::Netmail, pkt::{self, Packet}};
+use crate::database::{self as db, NetmailLink};
+use crate::error::{Error, Result};
+use crate::lang::{Key, t, tf};
+
+pub struct Config {
+   pub addr: Addr,
+   pub uplink: Addr,
+   pub password: String,
+   pub area: Option<String>,
+   pub chat_id: Option<i64>,
+   pub outbound: PathBuf,
+   pub inbound: Option<PathBuf>,
+   pub origin: String,
+   pub charset: Charset,
+   pub binkp_uplink: Option<String>,
+   pub binkp_password: String,
+   pub poll_interval: Duration,
+   pub binkp_listen: Option<String>,
+   pub links: Vec<Link>,
+   pub dupe_days: i64,
+}
+
+// Link with the session password, the host is for polling
+pub struct Link {
+   pub addr: Addr,
+   pub password: String,
+   pub host: Option<String>,
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+// How often to look for new packets
+const IMPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Telegram allows about 20 messages per minute to a group
+const POST_DELAY: Duration = Duration::from_secs(3);
+
+// Telegram limit is 4096 characters
+const POST_LEN: usize = 4000;
+
+// Parts of long posts already in the chat by the hash of the message,
+// after a transient failure the post continues from the first missing part
+static POSTED_PARTS: Lazy<Mutex<HashMap<String, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Unique numbers for MSGID and file names, starting from the current time
+// or after the serials reserved by the previous run, whichever is larger
+static SERIAL: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(Utc::now().timestamp() as u32));
+
+// Serials stored in the database ahead of use, a restart continues after them
+const SERIAL_RESERVE: u32 = 10000;
+const SERIAL_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn next_serial() -> u32 {
+   SERIAL.fetch_add(1, Ordering::SeqCst)
+}
+
+// Restore serials after a restart and keep the reservation ahead of the counter
+pub async fn serial_loop() {
+   if config().is_none() {
+      return;
+   }
+
+   let mut reserved = None;
+   loop {
+      if db::is_available() {
+         if let Err(e) = reserve_serials(&mut reserved).await {
+            log::info!("Error reserve_serials(): {}", e);
+         }
+      }
+      tokio::time::sleep(SERIAL_INTERVAL).await;
+   }
+}
+
+async fn reserve_serials(reserved: &mut Option<u32>) -> Result<()> {
+   // Serials up to the previous reservation may be used already
+   if reserved.is_none() {
+      let last = db::ftn_serial().await?;
+      let last = u32::try_from(last).map_err(|_| Error::Ftn("stored serial out of range"))?;
+      SERIAL.fetch_max(last, Ordering::SeqCst);
+      log::info!("Serials continue from {:08x}", SERIAL.load(Ordering::SeqCst));
+   }
+
+   // New reservation when half of the previous one is used
+   let current = SERIAL.load(Ordering::SeqCst);
+   if reserved.is_none_or(|r| current.saturating_add(SERIAL_RESERVE / 2) > r) {
+      let upto = current.saturating_add(SERIAL_RESERVE);
+      db::reserve_ftn_serial(upto as i64).await?;
+      *reserved = Some(upto);
+   }
+   Ok(())
+}
+
+// Gateway works only with FTN_ADDRESS
+pub fn init() -> Result<()> {
+   let addr = match env::var("FTN_ADDRESS") {
+      Ok(addr) if !addr.is_empty() => addr.parse()?,
+      _ => {
+         log::info!("FTN_ADDRESS is not set, gateway is disabled");
+         return Ok(());
+      }
+   };
+
+   let var = |name: &'static str, error: &'static str| env::var(name).map_err(|_| Error::Settings(error));
+
+   let mut config = Config {
+      addr,
+      uplink: var("FTN_UPLINK", "FTN_UPLINK env variable missing")?.parse()?,
+      password: env::var("FTN_PASSWORD").unwrap_or_default(),
+      // The first area, others are set by the admin
+      area: env::var("FTN_AREA").ok().filter(|s| !s.is_empty()).map(|s| s.to_uppercase()),
+      chat_id: env::var("FTN_CHAT_ID").ok().map(|s| s.parse()).transpose()
+         .map_err(|_| Error::Settings("FTN_CHAT_ID value to be integer"))?,
+      outbound: PathBuf::from(var("FTN_OUTBOUND", "FTN_OUTBOUND env variable missing")?),
+      inbound: env::var("FTN_INBOUND").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+      origin: env::var("FTN_ORIGIN").unwrap_or_else(|_| String::from("Telegram gateway of 2:5011")),
+      // Most of the Russian echoes are in CP866
+      charset: env::var("FTN_CHARSET").map_or(Ok(Charset::Cp866), |s| s.parse())?,
+      // Session password is the packet one by default
+      binkp_uplink: env::var("FTN_BINKP_UPLINK").ok().filter(|s| !s.is_empty()),
+      binkp_password: env::var("FTN_BINKP_PASSWORD").or_else(|_| env::var("FTN_PASSWORD")).unwrap_or_default(),
+      poll_interval: Duration::from_secs(60 * env::var("FTN_POLL_INTERVAL").ok()
+         .and_then(|s| s.parse().ok())
+         .unwrap_or(15)),
+      binkp_listen: env::var("FTN_BINKP_LISTEN").ok().filter(|s| !s.is_empty()),
+      links: env::var("FTN_LINKS").map_or(Ok(Vec::new()), |s| parse_links(&s))?,
+      dupe_days: env::var("FTN_DUPE_DAYS").ok().and_then(|s| s.parse().ok()).unwrap_or(30),
+   };
+
+   std::fs::create_dir_all(&config.outbound)?;
+
+   // Flow files list bundles by the full path
+   config.outbound = std::fs::canonicalize(&config.outbound)?;
+   log::info!("Gateway {} via {}", config.addr, config.uplink);
+
+   CONFIG.set(config).map_err(|_| Error::Settings("FTN settings are already set"))
+}
+
+// Links with session passwords and optional hosts like "2:5011/1.5 secret, 2:5020/100 other f100.n5020.example.org"
+fn parse_links(s: &str) -> Result<Vec<Link>> {
+   s.split(',')
+   .filter(|link| !link.trim().is_empty())
+   .map(|link| {
+      let mut parts = link.split_whitespace();
+      let addr = parts.next().ok_or(Error::Settings("FTN_LINKS expected address and password"))?.parse()?;
+      let password = parts.next().ok_or(Error::Settings("FTN_LINKS expected address and password"))?;
+      let host = parts.next().map(String::from);
+      Ok(Link { addr, password: String::from(password), host })
+   })
+   .collect()
+}
+
+impl Config {
+   // Host and session password of the uplink for the built-in mailer
+   pub fn uplink_host(&self, addr: Addr) -> Option<(&str, &str)> {
+      if let (true, Some(host)) = (addr == self.uplink, &self.binkp_uplink) {
+         return Some((host, &self.binkp_password));
+      }
+      self.links.iter()
+      .find(|link| link.addr == addr)
+      .and_then(|link| Some((link.host.as_deref()?, link.password.as_str())))
+   }
+
+   // The built-in mailer polls uplinks, otherwise the outbound is for another mailer
+   pub fn is_polling(&self) -> bool {
+      self.binkp_uplink.is_some() || self.links.iter().any(|link| link.host.is_some())
+   }
+
+   // Mail for the uplink leaves the outbound
+   pub fn is_reachable(&self, addr: Addr) -> bool {
+      !self.is_polling() || self.uplink_host(addr).is_some()
+   }
+}
+
+pub fn config() -> Option<&'static Config> {
+   CONFIG.get()
+}
+
+// Messages of this chat go to the echo area
+pub fn is_export_chat(chat_id: i64) -> bool {
+   config().is_some() && areas::is_area_chat(chat_id)
+}
+
+// Name of the Telegram user for the From field
+pub fn user_name(user: &User) -> String {
+   let name = user.full_name();
+   if name.trim().is_empty() {
+      user.username.clone().unwrap_or_else(|| format!("User {}", user.id))
+   } else {
+      name
+   }
+}
+
+// Moderation and bot commands stay in Telegram
+fn is_exportable(text: &str) -> bool {
+   !text.trim().is_empty() && text != "[+]" && !text.starts_with('/')
+}
+
+// Message of the user from the gateway chat, the origin is from the database if available
+pub async fn export_user_message(user: &User, chat_id: i64, message_id: i32, reply_to: Option<&Message>, text: &str, time: DateTime<Utc>) -> Result<()> {
+   if !is_exportable(text) {
+      return Ok(());
+   }
+
+   // Replied message knows its MSGID and area, in forums it may be any message of the topic
+   let reply_to_id = reply_to.map(|msg| msg.id);
+   let (reply, tag) = match reply_to_id {
+      Some(id) if db::is_available() => db::ftn_message(chat_id, id).await.unwrap_or_else(|e| {
+         log::info!("Error ftn_message() for {}: {}", id, e);
+         None
+      })
+      .map_or((None, None), |(msgid, tag)| (Some(msgid), tag)),
+      _ => (None, None),
+   };
+   let area = tag.and_then(|tag| areas::by_tag(&tag))
+   .filter(|area| area.chat_id == chat_id)
+   .or_else(|| areas::for_message(chat_id, reply_to_id));
+
+   // Read-only areas are for reading FidoNet in Telegram
+   let area = match area {
+      Some(area) if !area.read_only => area,
+      _ => return Ok(()),
+   };
+
+   let (descr, addr) = if db::is_available() {
+      db::user_origin(user.id).await.unwrap_or_else(|e| {
+         log::info!("Error user_origin() for {}: {}", user.id, e);
+         (None, None)
+      })
+   } else {
+      (None, None)
+   };
+
+   // Telegram may deliver the same update again
+   let hash = dupes::hash(&[&area.tag, &chat_id.to_string(), &message_id.to_string()]);
+   if dupes::is_dupe(&area.tag, "", &hash, Direction::Export).await {
+      return Ok(());
+   }
+
+   // The first message of the topic is not a reply
+   let quoted = reply_to.filter(|msg| area.thread_id != Some(msg.id)).and_then(replied);
+   let (to, body) = match quoted {
+      Some((to, quoted)) => (to.clone(), reply_body(&area, &to, &quoted, text)),
+      None => (String::from("All"), String::from(text)),
+   };
+
+   let msgid = export(&area, &user_name(user), &to, &body, time, (descr.as_deref(), addr.as_deref()), reply).await?;
+   remember(&area, message_id, &msgid).await;
+   dupes::remember(&area.tag, &msgid, &hash).await;
+   Ok(())
+}
+
+// Author and text of the replied message, posts of the gateway have the FidoNet author in the header
+fn replied(msg: &Message) -> Option<(String, String)> {
+   let text = msg.text()?;
+   let from = msg.from()?;
+   if from.is_bot {
+      parse_post(text)
+   } else {
+      Some((user_name(from), String::from(text)))
+   }
+}
+
+// Greeting and quote by the settings of the area before the answer
+fn reply_body(area: &Area, to: &str, quoted: &str, text: &str) -> String {
+   let mut parts = Vec::new();
+   if !area.greeting.is_empty() {
+      parts.push(quote::greeting(&area.greeting, to));
+   }
+   if area.quote {
+      let quoted = quote::quote(quoted, &quote::initials(to));
+      if !quoted.is_empty() {
+         parts.push(quoted);
+      }
+   }
+   parts.push(String::from(text));
+   parts.join("\n\n")
+}
+
+// Mapping for threading and areas of replies, the gateway works without it
+async fn remember(area: &Area, message_id: i32, msgid: &str) {
+   if msgid.is_empty() || !db::is_available() {
+      return;
+   }
+   if let Err(e) = db::save_ftn_message(area.chat_id, message_id, msgid, &area.tag).await {
+      log::info!("Error save_ftn_message() for {}: {}", msgid, e);
+   }
+}
+
+// Message from the chat to the outbound directory, descr and addr of the origin are from the users table, returns MSGID
+pub async fn export(area: &Area, from: &str, to: &str, text: &str, time: DateTime<Utc>, (descr, addr): (Option<&str>, Option<&str>), reply: Option<String>) -> Result<String> {
+   let config = config().ok_or(Error::Settings("gateway is disabled"))?;
+
+   // Origin of the user if known, otherwise of the gateway
+   let origin_addr = addr.and_then(Addr::find).unwrap_or(config.addr);
+   let origin = descr.filter(|s| !s.is_empty()).unwrap_or(&config.origin);
+
+   let subject: String = text.lines().next().unwrap_or_default().chars().take(71).collect();
+
+   let serial = next_serial();
+   let mut mail = Echomail {
+      area: area.tag.clone(),
+      from: String::from(from),
+      to: String::from(to),
+      subject,
+      msgid: format!("{} {:08x}", config.addr, serial),
+      reply,
+      body: String::from(text),
+      tearline: format!("N5011_bot {}", env!("CARGO_PKG_VERSION")),
+      origin: String::from(origin),
+      origin_addr,
+      seen_by: Vec::new(),
+      path: Vec::new(),
+   };
+
+   // Points are hidden behind the boss, which adds itself
+   if config.addr.point == 0 {
+      mail.add_seen_by(&[config.addr]);
+      mail.add_path(config.addr);
+   }
+   mail.add_seen_by(&[area.uplink]);
+
+   let encode = |s: &str| {
+      let (bytes, lossy) = config.charset.encode(s);
+      if lossy {
+         log::info!("Some characters of {} are not in {:?}", mail.msgid, config.charset);
+      }
+      bytes
+   };
+
+   let mut packet = Packet::new(config.addr, area.uplink, &config.password);
+   packet.messages.push(pkt::Message {
+      orig: config.addr,
+      dest: area.uplink,
+      attr: 0,
+      date: pkt::fts_date(time),
+      to: encode(&mail.to),
+      from: encode(&mail.from),
+      subject: encode(&mail.subject),
+      text: encode(&mail.text(config.charset.chrs())),
+   });
+
+   write_packet(&config.outbound.join(format!("{:08x}.pkt", serial)), &packet).await?;
+   log::info!("Exported {} to {}", mail.msgid, area.tag);
+   Ok(mail.msgid)
+}
+
+// Temporary name first so the mailer never sees a partial packet,
+// the link fails instead of replacing a packet with the same name
+async fn write_packet(path: &Path, packet: &Packet) -> Result<()> {
+   let tmp = path.with_extension("tmp");
+   tokio::fs::write(&tmp, packet.to_bytes()).await?;
+   let res = tokio::fs::hard_link(&tmp, path).await;
+   tokio::fs::remove_file(&tmp).await?;
+   res?;
+   Ok(())
+}
+
+// The rest of the packet in place of the original one
+async fn replace_packet(path: &Path, packet: &Packet) -> Result<()> {
+   let tmp = path.with_extension("tmp");
+   tokio::fs::write(&tmp, packet.to_bytes()).await?;
+   tokio::fs::rename(&tmp, path).await?;
+   Ok(())
+}
+
+// Packet with the same header and other messages
+fn with_messages(packet: &Packet, messages: Vec<pkt::Message>) -> Packet {
+   Packet {
+      orig: packet.orig,
+      dest: packet.dest,
+      date: packet.date,
+      password: packet.password.clone(),
+      messages,
+   }
+}
+
+// Periodic import of packets from FTN_INBOUND into the chat
+pub async fn import_loop(bot: AutoSend<Bot>) {
+   let (config, inbound) = match config() {
+      Some(config) => match &config.inbound {
+         Some(inbound) => (config, inbound),
+         None => return,
+      },
+      None => return,
+   };
+
+   loop {
+      if let Err(e) = import(&bot, config, inbound).await {
+         log::info!("Error import from {}: {}", inbound.display(), e);
+      }
+      tokio::time::sleep(IMPORT_INTERVAL).await;
+   }
+}
+
+async fn import(bot: &AutoSend<Bot>, config: &Config, dir: &Path) -> Result<()> {
+   bso::unpack(dir).await?;
+
+   let mut files = Vec::new();
+   let mut entries = tokio::fs::read_dir(dir).await?;
+   while let Some(entry) = entries.next_entry().await? {
+      let path = entry.path();
+      let is_pkt = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pkt"));
+      if is_pkt {
+         files.push(path);
+      }
+   }
+   files.sort();
+
+   for path in files {
+      let data = tokio::fs::read(&path).await?;
+      let mut packet = match Packet::parse(&data) {
+         Ok(packet) if is_packet_password(config, &packet) => packet,
+         Ok(packet) => {
+            log::info!("Wrong password of packet {} from {}", path.display(), packet.orig);
+            tokio::fs::rename(&path, path.with_extension("sec")).await?;
+            continue;
+         }
+         Err(e) => {
+            log::info!("Bad packet {}: {}", path.display(), e);
+            tokio::fs::rename(&path, path.with_extension("bad")).await?;
+            continue;
+         }
+      };
+
+      // Each message separately, so a permanent failure does not hold up the rest
+      let mut retry = Vec::new();
+      let mut failed = Vec::new();
+      let mut stopped = false;
+      let mut messages = std::mem::take(&mut packet.messages).into_iter();
+      while let Some(message) = messages.next() {
+         match post_message(bot, config, &message).await {
+            Ok(()) => {}
+            // Netmail waits for the database without holding up echomail
+            Err(e) if e.is_transient() && !message.is_echomail() => {
+               log::info!("Netmail from {} in {} is postponed: {}", message.orig, path.display(), e);
+               retry.push(message);
+            }
+            Err(e) if e.is_transient() => {
+               log::info!("Import of {} is postponed: {}", path.display(), e);
+               retry.push(message);
+               retry.extend(messages.by_ref());
+               stopped = true;
+            }
+            Err(e) => {
+               log::info!("Message from {} in {} failed: {}", message.orig, path.display(), e);
+               failed.push(message);
+            }
+         }
+      }
+
+      // Failed messages are kept for the sysop
+      if !failed.is_empty() {
+         let bad = dir.join(format!("{:08x}.bad", next_serial()));
+         write_packet(&bad, &with_messages(&packet, failed)).await?;
+      }
+
+      // Only the rest of the packet is posted next time
+      if retry.is_empty() {
+         tokio::fs::remove_file(&path).await?;
+      } else {
+         replace_packet(&path, &with_messages(&packet, retry)).await?;
+      }
+
+      // Echomail of later packets waits to keep the order
+      if stopped {
+         break;
+      }
+   }
+   Ok(())
+}
+
+// Packets of links have their own passwords, others are from the uplink,
+// only 8 characters fit the header
+fn is_packet_password(config: &Config, packet: &Packet) -> bool {
+   let expected = config.links.iter()
+   .find(|link| link.addr == packet.orig)
+   .map_or(config.password.as_str(), |link| link.password.as_str());
+   let expected: String = expected.chars().take(8).collect();
+   expected.is_empty() || expected == "-" || packet.password.eq_ignore_ascii_case(&expected)
+}
+
+async fn post_message(bot: &AutoSend<Bot>, config: &Config, message: &pkt::Message) -> Result<()> {
+   let (from, to, subject, text) = decode_message(message, config.charset);
+   let (mail, area) = match Echomail::parse(&from, &to, &subject, &text, message.orig) {
+      Some(mail) => match areas::by_tag(&mail.area) {
+         Some(area) => (mail, area),
+         None => return Ok(()),
+      },
+      None => {
+         let mail = Netmail::parse(&from, &to, &subject, &text, message.orig, message.dest);
+         return deliver_netmail(bot, mail).await;
+      }
+   };
+
+   // Own messages come back from the uplink
+   if Addr::find(&mail.msgid) == Some(config.addr) {
+      return Ok(());
+   }
+
+   // Loop through the links back to us
+   if config.addr.point == 0 && mail.has_path(config.addr) {
+      log::info!("Skipped {} from {}, PATH contains {}", mail.msgid, mail.area, config.addr);
+      return Ok(());
+   }
+
+   // The same message through different links
+   let hash = dupes::hash(&[&area.tag, &mail.from, &mail.to, &mail.subject, &mail.body]);
+   if dupes::is_dupe(&area.tag, &mail.msgid, &hash, Direction::Import).await {
+      return Ok(());
+   }
+
+   // Reply to the known message keeps the thread, others go to the topic of the area
+   let reply_to = match &mail.reply {
+      Some(reply) if db::is_available() => db::ftn_message_id(area.chat_id, reply).await.unwrap_or_else(|e| {
+         log::info!("Error ftn_message_id() for {}: {}", reply, e);
+         None
+      }),
+      _ => None,
+   };
+   let reply_to = reply_to.or(area.thread_id);
+
+   let posted = POSTED_PARTS.lock().unwrap().get(&hash).copied().unwrap_or(0);
+   for (i, part) in split_post(&post_text(&mail)).into_iter().enumerate().skip(posted) {
+      // The replied message may be deleted already
+      let request = bot.send_message(area.chat_id, part).allow_sending_without_reply(true);
+      let sent = match reply_to {
+         Some(id) => request.reply_to_message_id(id).await,
+         None => request.await,
+      };
+      let sent = match sent.map_err(Error::from) {
+         Ok(sent) => sent,
+         Err(e) => {
+            // The failed message is parked, it is not posted again
+            if !e.is_transient() {
+               POSTED_PARTS.lock().unwrap().remove(&hash);
+            }
+            return Err(e);
+         }
+      };
+      POSTED_PARTS.lock().unwrap().insert(hash.clone(), i + 1);
+      remember(&area, sent.id, &mail.msgid).await;
+      tokio::time::sleep(POST_DELAY).await;
+   }
+   POSTED_PARTS.lock().unwrap().remove(&hash);
+   dupes::remember(&area.tag, &mail.msgid, &hash).await;
+   log::info!("Imported {} from {}", mail.msgid, mail.area);
+   Ok(())
+}
+
+// Linked user by the nodelist address
+async fn find_user(addr: Addr) -> Result<Option<i64>> {
+   let users = db::users_addr().await?;
+   Ok(users.into_iter()
+   .find(|(_, addrs)| Addr::find_all(addrs).contains(&addr))
+   .map(|(user_id, _)| user_id))
+}
+
+// Private message to the user with the address, the database is required to reply
+async fn deliver_netmail(bot: &AutoSend<Bot>, mail: Netmail) -> Result<()> {
+   if !db::is_available() {
+      return Err(Error::Unavailable);
+   }
+
+   let user_id = match find_user(mail.dest).await? {
+      Some(user_id) => user_id,
+      None => {
+         log::info!("Netmail {} to {} is not for our users", mail.msgid, mail.dest);
+         return Ok(());
+      }
+   };
+
+   let lang = db::user_lang(user_id).await?.unwrap_or_default();
+   let mut text = tf(lang, Key::NetmailHeader, &[&mail.from, &mail.orig, &mail.to, &mail.dest]);
+   if !mail.subject.is_empty() {
+      text = format!("{}\nSubj: {}", text, mail.subject);
+   }
+   text = format!("{}\n\n{}\n\n{}", text, mail.body, t(lang, Key::NetmailReplyHint));
+
+   let link = NetmailLink {
+      msgid: mail.msgid.clone(),
+      from_name: mail.from.clone(),
+      from_addr: mail.orig.to_string(),
+      to_addr: mail.dest.to_string(),
+      subject: mail.subject.clone(),
+   };
+
+   for (i, part) in split_post(&text).into_iter().enumerate() {
+      match bot.send_message(user_id, part).await.map_err(Error::from) {
+         // Without the link the user only cannot reply, the netmail is not sent again
+         Ok(sent) => if let Err(e) = db::save_netmail(user_id, sent.id, &link).await {
+            log::info!("Error save_netmail() for {}: {}", mail.msgid, e);
+         },
+         // Nothing is delivered yet, so the next time
+         Err(e) if i == 0 && e.is_transient() => return Err(e),
+         // The user may have blocked the bot, such netmail is not retried
+         Err(e) => {
+            log::info!("Error deliver netmail {} to {}: {}", mail.msgid, user_id, e);
+            return Ok(());
+         }
+      }
+   }
+   log::info!("Netmail {} delivered to {}", mail.msgid, user_id);
+   Ok(())
+}
+
+// Reply to the delivered netmail from the address of the user, false if it is not such reply
+pub async fn reply_netmail(user: &User, chat_id: i64, reply_to: i32, text: &str, time: DateTime<Utc>) -> Result<bool> {
+   let config = match config() {
+      Some(config) if db::is_available() => config,
+      _ => return Ok(false),
+   };
+
+   let link = match db::netmail(chat_id, reply_to).await? {
+      Some(link) => link,
+      None => return Ok(false),
+   };
+
+   let orig: Addr = link.to_addr.parse()?;
+   let subject = if link.subject.starts_with("Re:") { link.subject.clone() } else { format!("Re: {}", link.subject) };
+   let serial = next_serial();
+   let mail = Netmail {
+      from: user_name(user),
+      to: link.from_name.clone(),
+      subject,
+      orig,
+      dest: link.from_addr.parse()?,
+      msgid: format!("{} {:08x}", orig, serial),
+      reply: Some(link.msgid.clone()),
+      body: String::from(text),
+   };
+
+   let encode = |s: &str| config.charset.encode(s).0;
+   let tearline = format!("N5011_bot {}", env!("CARGO_PKG_VERSION"));
+
+   let mut packet = Packet::new(config.addr, config.uplink, &config.password);
+   packet.messages.push(pkt::Message {
+      orig: mail.orig,
+      dest: mail.dest,
+      attr: pkt::ATTR_PRIVATE,
+      date: pkt::fts_date(time),
+      to: encode(&mail.to),
+      from: encode(&mail.from),
+      subject: encode(&mail.subject),
+      text: encode(&mail.text(config.charset.chrs(), &tearline)),
+   });
+
+   write_packet(&config.outbound.join(format!("{:08x}.pkt", serial)), &packet).await?;
+   log::info!("Netmail {} from {} to {}", mail.msgid, mail.orig, mail.dest);
+   Ok(true)
+}
+
+// By the CHRS kludge, without it in the default charset
+fn decode_message(message: &pkt::Message, default: Charset) -> (String, String, String, String) {
+   let charset = charset::detect(&message.text).unwrap_or(default);
+   let mut lossy = false;
+   let mut decode = |bytes: &[u8]| {
+      let (text, failed) = charset.decode(bytes);
+      lossy |= failed;
+      text
+   };
+   let res = (decode(&message.from), decode(&message.to), decode(&message.subject), decode(&message.text));
+   if lossy {
+      log::info!("Some characters of the message from {} are not in {:?}", message.orig, charset);
+   }
+   res
+}
+
+// Author, subject, text and the original origin line
+fn post_text(mail: &Echomail) -> String {
+   let mut header = format!("{}, {}", mail.from, mail.origin_addr);
+   if !mail.to.is_empty() && !mail.to.eq_ignore_ascii_case("All") {
+      header = format!("{} → {}", header, mail.to);
+   }
+   if !mail.subject.is_empty() {
+      header = format!("{}\nSubj: {}", header, mail.subject);
+   }
+
+   let mut text = format!("{}\n\n{}", header, mail.body);
+   if !mail.origin.is_empty() {
+      text = format!("{}\n\n * Origin: {} ({})", text, mail.origin, mail.origin_addr);
+   }
+   text
+}
+
+// Author and text of the post made by post_text(), None for other messages of the bot
+fn parse_post(text: &str) -> Option<(String, String)> {
+   let mut lines = text.lines();
+   let header = lines.next()?;
+   let header = header.split(" → ").next().unwrap_or(header);
+   let (from, addr) = header.rsplit_once(", ")?;
+   addr.parse::<Addr>().ok()?;
+
+   // Subject is up to the empty line, the origin ends the text
+   let body: Vec<&str> = lines
+   .skip_while(|line| !line.is_empty())
+   .take_while(|line| !line.starts_with(" * Origin: "))
+   .collect();
+   Some((String::from(from), body.join("\n").trim().to_string()))
+}
+
+// Long messages by lines, too long lines by characters
+fn split_post(text: &str) -> Vec<String> {
+   let mut parts = Vec::new();
+   let mut part = String::new();
+   for line in text.lines() {
+      let mut line: Vec<char> = line.chars().collect();
+      loop {
+         let room = POST_LEN.saturating_sub(part.chars().count());
+         if line.len() < room {
+            part.extend(line.iter());
+            part.push('\n');
+            break;
+         }
+         if part.is_empty() {
+            let rest = line.split_off(POST_LEN);
+            parts.push(line.into_iter().collect());
+            line = rest;
+         } else {
+            parts.push(std::mem::take(&mut part));
+         }
+      }
+   }
+   if !part.trim().is_empty() {
+      parts.push(part);
+   }
+   parts
+}
@@ -0,0 +1,22 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+FidoNet technology. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+pub mod addr;
+pub mod pkt;
+pub mod charset;
+pub mod echomail;
+pub mod netmail;
+pub mod areas;
+pub mod dupes;
+pub mod gateway;
+pub mod quote;
+pub mod binkp;
+pub mod bso;
+
+pub use addr::Addr;
@@ -0,0 +1,91 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Netmail messages, FTS-4001. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use super::Addr;
+
+pub struct Netmail {
+   pub from: String,
+   pub to: String,
+   pub subject: String,
+   pub orig: Addr,
+   pub dest: Addr,
+   pub msgid: String,
+   pub reply: Option<String>,
+   pub body: String,
+}
+
+impl Netmail {
+   // Packed message has 2D addresses, full ones are in INTL, FMPT and TOPT
+   pub fn parse(from: &str, to: &str, subject: &str, text: &str, orig: Addr, dest: Addr) -> Self {
+      let mut mail = Self {
+         from: String::from(from),
+         to: String::from(to),
+         subject: String::from(subject),
+         orig,
+         dest,
+         msgid: String::new(),
+         reply: None,
+         body: String::new(),
+      };
+
+      let mut body = Vec::new();
+      for line in text.split('\r').map(|line| line.trim_start_matches('\n')) {
+         let kludge = match line.strip_prefix('\x01') {
+            Some(kludge) => kludge,
+            None => {
+               body.push(line);
+               continue;
+            }
+         };
+
+         if let Some(intl) = kludge.strip_prefix("INTL ") {
+            let mut addrs = intl.split_whitespace().filter_map(|s| s.parse::<Addr>().ok());
+            if let (Some(to), Some(from)) = (addrs.next(), addrs.next()) {
+               mail.dest = Addr { point: mail.dest.point, ..to };
+               mail.orig = Addr { point: mail.orig.point, ..from };
+            }
+         } else if let Some(point) = kludge.strip_prefix("FMPT ") {
+            mail.orig.point = point.trim().parse().unwrap_or_default();
+         } else if let Some(point) = kludge.strip_prefix("TOPT ") {
+            mail.dest.point = point.trim().parse().unwrap_or_default();
+         } else if let Some(msgid) = kludge.strip_prefix("MSGID:") {
+            mail.msgid = String::from(msgid.trim());
+         } else if let Some(reply) = kludge.strip_prefix("REPLY:") {
+            mail.reply = Some(String::from(reply.trim()));
+         }
+      }
+
+      mail.body = body.join("\n").trim().to_string();
+      mail
+   }
+
+   // Text of the packed message with CR as the line separator
+   pub fn text(&self, chrs: &str, tearline: &str) -> String {
+      let mut lines = vec![format!("\x01INTL {} {}", self.dest.boss(), self.orig.boss())];
+      if self.orig.point != 0 {
+         lines.push(format!("\x01FMPT {}", self.orig.point));
+      }
+      if self.dest.point != 0 {
+         lines.push(format!("\x01TOPT {}", self.dest.point));
+      }
+      lines.push(format!("\x01MSGID: {}", self.msgid));
+      if let Some(reply) = &self.reply {
+         lines.push(format!("\x01REPLY: {}", reply));
+      }
+      lines.push(format!("\x01CHRS: {}", chrs));
+      lines.push(String::from("\x01TZUTC: 0000"));
+
+      lines.extend(self.body.lines().map(String::from));
+      lines.push(format!("--- {}", tearline));
+
+      let mut text = lines.join("\r");
+      text.push('\r');
+      text
+   }
+}
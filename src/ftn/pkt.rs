@@ -0,0 +1,422 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Type 2+ packets, FTS-0001 and FSC-0039. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+
+use super::Addr;
+use crate::error::{Error, Result};
+
+// Capability word of type 2+
+const CAPABILITY: u16 = 0x0001;
+
+// No product code assigned
+const PRODUCT_CODE: u8 = 0xfe;
+
+const HEADER_LEN: usize = 58;
+
+// Limits of the packed message fields including the terminating zero
+const NAME_LEN: usize = 36;
+const SUBJECT_LEN: usize = 72;
+const DATE_LEN: usize = 20;
+
+pub const ATTR_PRIVATE: u16 = 0x0001;
+
+pub struct Packet {
+   pub orig: Addr,
+   pub dest: Addr,
+   pub date: DateTime<Utc>,
+   pub password: String,
+   pub messages: Vec<Message>,
+}
+
+// Packed message, strings are already in the charset of the message
+pub struct Message {
+   pub orig: Addr,
+   pub dest: Addr,
+   pub attr: u16,
+   pub date: String,
+   pub to: Vec<u8>,
+   pub from: Vec<u8>,
+   pub subject: Vec<u8>,
+   pub text: Vec<u8>,
+}
+
+// Date and time as "18 Oct 26  12:34:56"
+pub fn fts_date(date: DateTime<Utc>) -> String {
+   date.format("%d %b %y  %H:%M:%S").to_string()
+}
+
+impl Packet {
+   pub fn new(orig: Addr, dest: Addr, password: &str) -> Self {
+      Self {
+         orig,
+         dest,
+         date: Utc::now(),
+         password: String::from(password),
+         messages: Vec::new(),
+      }
+   }
+
+   pub fn to_bytes(&self) -> Vec<u8> {
+      let mut buf = Vec::new();
+
+      let put = |buf: &mut Vec<u8>, n: u16| buf.extend_from_slice(&n.to_le_bytes());
+
+      // Header
+      put(&mut buf, self.orig.node);
+      put(&mut buf, self.dest.node);
+      put(&mut buf, self.date.year() as u16);
+      put(&mut buf, self.date.month0() as u16);
+      put(&mut buf, self.date.day() as u16);
+      put(&mut buf, self.date.hour() as u16);
+      put(&mut buf, self.date.minute() as u16);
+      put(&mut buf, self.date.second() as u16);
+      put(&mut buf, 0); // baud
+      put(&mut buf, 2); // packet type
+      put(&mut buf, self.orig.net);
+      put(&mut buf, self.dest.net);
+      buf.push(PRODUCT_CODE);
+      buf.push(0); // revision major
+      let mut password = [0u8; 8];
+      for (i, b) in self.password.bytes().take(8).enumerate() {
+         password[i] = b;
+      }
+      buf.extend_from_slice(&password);
+      put(&mut buf, self.orig.zone);
+      put(&mut buf, self.dest.zone);
+      put(&mut buf, 0); // aux net
+      put(&mut buf, CAPABILITY.swap_bytes());
+      buf.push(0); // product code high
+      buf.push(0); // revision minor
+      put(&mut buf, CAPABILITY);
+      put(&mut buf, self.orig.zone);
+      put(&mut buf, self.dest.zone);
+      put(&mut buf, self.orig.point);
+      put(&mut buf, self.dest.point);
+      buf.extend_from_slice(&[0; 4]); // product data
+
+      for message in &self.messages {
+         message.write(&mut buf);
+      }
+
+      // End of packet
+      put(&mut buf, 0);
+      buf
+   }
+}
+
+// Little-endian reader over the packet
+struct Reader<'a> {
+   data: &'a [u8],
+   pos: usize,
+}
+
+impl<'a> Reader<'a> {
+   fn u8(&mut self) -> Result<u8> {
+      let b = *self.data.get(self.pos).ok_or(Error::Ftn("unexpected end of packet"))?;
+      self.pos += 1;
+      Ok(b)
+   }
+
+   fn u16(&mut self) -> Result<u16> {
+      Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+   }
+
+   fn u16_at(&self, pos: usize) -> u16 {
+      u16::from_le_bytes([self.data[pos], self.data[pos + 1]])
+   }
+
+   // Null-terminated string, no longer than the limit
+   fn string(&mut self, limit: usize) -> Result<Vec<u8>> {
+      let mut s = Vec::new();
+      loop {
+         match self.u8()? {
+            0 => return Ok(s),
+            b if s.len() + 1 < limit => s.push(b),
+            _ => return Err(Error::Ftn("too long field of packed message")),
+         }
+      }
+   }
+
+   // Text up to zero or the end of a broken packet
+   fn text(&mut self) -> Vec<u8> {
+      let rest = &self.data[self.pos..];
+      let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+      self.pos += len + 1;
+      rest[..len].to_vec()
+   }
+}
+
+impl Packet {
+   pub fn parse(data: &[u8]) -> Result<Self> {
+      if data.len() < HEADER_LEN {
+         return Err(Error::Ftn("packet is shorter than header"));
+      }
+
+      let mut r = Reader { data, pos: 0 };
+      let orig_node = r.u16()?;
+      let dest_node = r.u16()?;
+      let (year, month, day) = (r.u16()?, r.u16()?, r.u16()?);
+      let (hour, minute, second) = (r.u16()?, r.u16()?, r.u16()?);
+      let _baud = r.u16()?;
+      if r.u16()? != 2 {
+         return Err(Error::Ftn("packet is not of type 2"));
+      }
+      let mut orig_net = r.u16()?;
+      let dest_net = r.u16()?;
+      let password: Vec<u8> = data[26..34].iter().copied().take_while(|b| *b != 0).collect();
+
+      // Zones and points only in type 2+
+      let cap_valid = r.u16_at(40);
+      let cap_word = r.u16_at(44);
+      let plus = cap_word == cap_valid.swap_bytes() && cap_word & CAPABILITY != 0;
+      let (orig_zone, dest_zone, orig_point, dest_point) = if plus {
+         (r.u16_at(46), r.u16_at(48), r.u16_at(50), r.u16_at(52))
+      } else {
+         (r.u16_at(34), r.u16_at(36), 0, 0)
+      };
+
+      // FSC-0048 puts the net of a point to the aux net
+      if plus && orig_point != 0 && orig_net == 0xffff {
+         orig_net = r.u16_at(38);
+      }
+
+      let date = Utc.with_ymd_and_hms(year as i32, month as u32 + 1, day as u32, hour as u32, minute as u32, second as u32)
+      .single()
+      .unwrap_or_else(Utc::now);
+
+      let mut packet = Packet {
+         orig: Addr::new(orig_zone, orig_net, orig_node, orig_point),
+         dest: Addr::new(dest_zone, dest_net, dest_node, dest_point),
+         date,
+         password: String::from_utf8_lossy(&password).into_owned(),
+         messages: Vec::new(),
+      };
+
+      r.pos = HEADER_LEN;
+      loop {
+         // Some packers omit the final zero
+         if r.pos >= data.len() {
+            break;
+         }
+         match r.u16()? {
+            0 => break,
+            2 => {
+               let message = Message::read(&mut r, orig_zone)?;
+               packet.messages.push(message);
+            }
+            _ => return Err(Error::Ftn("unknown type of packed message")),
+         }
+      }
+      Ok(packet)
+   }
+}
+
+impl Message {
+   // Echomail starts with the AREA line in any charset, FTS-0004
+   pub fn is_echomail(&self) -> bool {
+      self.text.iter().skip_while(|b| **b == b'\n').take(5).eq(b"AREA:")
+   }
+
+   // Packed messages have only 2D addresses, zone is from the packet
+   fn read(r: &mut Reader, zone: u16) -> Result<Self> {
+      let orig_node = r.u16()?;
+      let dest_node = r.u16()?;
+      let orig_net = r.u16()?;
+      let dest_net = r.u16()?;
+      let attr = r.u16()?;
+      let _cost = r.u16()?;
+
+      // Date should be 20 bytes with zero, but shorter ones occur
+      let date = r.string(DATE_LEN)?;
+      let to = r.string(NAME_LEN)?;
+      let from = r.string(NAME_LEN)?;
+      let subject = r.string(SUBJECT_LEN)?;
+      let text = r.text();
+
+      Ok(Self {
+         orig: Addr::new(zone, orig_net, orig_node, 0),
+         dest: Addr::new(zone, dest_net, dest_node, 0),
+         attr,
+         date: String::from_utf8_lossy(&date).into_owned(),
+         to,
+         from,
+         subject,
+         text,
+      })
+   }
+
+   fn write(&self, buf: &mut Vec<u8>) {
+      let put = |buf: &mut Vec<u8>, n: u16| buf.extend_from_slice(&n.to_le_bytes());
+
+      put(buf, 2); // message type
+      put(buf, self.orig.node);
+      put(buf, self.dest.node);
+      put(buf, self.orig.net);
+      put(buf, self.dest.net);
+      put(buf, self.attr);
+      put(buf, 0); // cost
+
+      // Fixed length date
+      let mut date = [0u8; DATE_LEN];
+      for (i, b) in self.date.bytes().take(DATE_LEN - 1).enumerate() {
+         date[i] = b;
+      }
+      buf.extend_from_slice(&date);
+
+      put_string(buf, &self.to, NAME_LEN);
+      put_string(buf, &self.from, NAME_LEN);
+      put_string(buf, &self.subject, SUBJECT_LEN);
+
+      // Zero is the end of the text
+      buf.extend(self.text.iter().filter(|b| **b != 0));
+      buf.push(0);
+   }
+}
+
+// Null-terminated string clipped to the limit
+fn put_string(buf: &mut Vec<u8>, s: &[u8], limit: usize) {
+   buf.extend(s.iter().filter(|b| **b != 0).take(limit - 1));
+   buf.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn u16_at(data: &[u8], pos: usize) -> u16 {
+      u16::from_le_bytes([data[pos], data[pos + 1]])
+   }
+
+   fn sample() -> Packet {
+      let mut packet = Packet::new(Addr::new(2, 5011, 1, 5), Addr::new(2, 5020, 100, 0), "secret12345");
+      packet.date = Utc.with_ymd_and_hms(2026, 10, 18, 12, 34, 56).unwrap();
+      packet.messages.push(Message {
+         orig: Addr::new(2, 5011, 1, 0),
+         dest: Addr::new(2, 5020, 100, 0),
+         attr: ATTR_PRIVATE,
+         date: fts_date(packet.date),
+         to: b"Artem Khomenko".to_vec(),
+         from: b"Sysop".to_vec(),
+         subject: b"Test".to_vec(),
+         text: b"AREA:N5020.BOT\rHello\r".to_vec(),
+      });
+      packet
+   }
+
+   #[test]
+   fn header_offsets() {
+      let mut packet = sample();
+      packet.messages.clear();
+      let data = packet.to_bytes();
+
+      assert_eq!(data.len(), HEADER_LEN + 2);
+      assert_eq!(u16_at(&data, 0), 1);
+      assert_eq!(u16_at(&data, 2), 100);
+      assert_eq!(u16_at(&data, 4), 2026);
+      // Month from zero
+      assert_eq!(u16_at(&data, 6), 9);
+      assert_eq!(u16_at(&data, 8), 18);
+      assert_eq!((u16_at(&data, 10), u16_at(&data, 12), u16_at(&data, 14)), (12, 34, 56));
+      assert_eq!(u16_at(&data, 18), 2);
+      assert_eq!(u16_at(&data, 20), 5011);
+      assert_eq!(u16_at(&data, 22), 5020);
+      assert_eq!(&data[26..34], b"secret12");
+      assert_eq!((u16_at(&data, 34), u16_at(&data, 36)), (2, 2));
+      // Capability word and its byte swapped copy
+      assert_eq!(u16_at(&data, 40), 0x0100);
+      assert_eq!(u16_at(&data, 44), 0x0001);
+      assert_eq!((u16_at(&data, 46), u16_at(&data, 48)), (2, 2));
+      assert_eq!((u16_at(&data, 50), u16_at(&data, 52)), (5, 0));
+      assert_eq!(&data[HEADER_LEN..], &[0, 0]);
+   }
+
+   #[test]
+   fn round_trip() {
+      let packet = Packet::parse(&sample().to_bytes()).unwrap();
+      assert_eq!(packet.orig, Addr::new(2, 5011, 1, 5));
+      assert_eq!(packet.dest, Addr::new(2, 5020, 100, 0));
+      assert_eq!(packet.date, Utc.with_ymd_and_hms(2026, 10, 18, 12, 34, 56).unwrap());
+      assert_eq!(packet.password, "secret12");
+      assert_eq!(packet.messages.len(), 1);
+
+      let message = &packet.messages[0];
+      assert_eq!(message.orig, Addr::new(2, 5011, 1, 0));
+      assert_eq!(message.dest, Addr::new(2, 5020, 100, 0));
+      assert_eq!(message.attr, ATTR_PRIVATE);
+      assert_eq!(message.date, "18 Oct 26  12:34:56");
+      assert_eq!(message.to, b"Artem Khomenko");
+      assert_eq!(message.from, b"Sysop");
+      assert_eq!(message.subject, b"Test");
+      assert_eq!(message.text, b"AREA:N5020.BOT\rHello\r");
+      assert!(message.is_echomail());
+   }
+
+   #[test]
+   fn netmail_without_area() {
+      let mut packet = sample();
+      packet.messages[0].text = b"\x01INTL 2:5020/100 2:5011/1\rHello\r".to_vec();
+      assert!(!packet.messages[0].is_echomail());
+   }
+
+   #[test]
+   fn message_fields() {
+      let mut packet = sample();
+      packet.messages[0].to = vec![b'a'; 40];
+      let data = packet.to_bytes();
+
+      // Type, addresses, attribute and cost, then the date of fixed length
+      let m = HEADER_LEN;
+      assert_eq!(u16_at(&data, m), 2);
+      assert_eq!((u16_at(&data, m + 2), u16_at(&data, m + 4)), (1, 100));
+      assert_eq!((u16_at(&data, m + 6), u16_at(&data, m + 8)), (5011, 5020));
+      assert_eq!(u16_at(&data, m + 10), ATTR_PRIVATE);
+      assert_eq!(&data[m + 14..m + 14 + DATE_LEN], b"18 Oct 26  12:34:56\0");
+
+      // Too long name is clipped with the terminating zero
+      let to = m + 14 + DATE_LEN;
+      assert_eq!(&data[to..to + NAME_LEN - 1], &[b'a'; NAME_LEN - 1][..]);
+      assert_eq!(data[to + NAME_LEN - 1], 0);
+
+      let packet = Packet::parse(&data).unwrap();
+      assert_eq!(packet.messages[0].to.len(), NAME_LEN - 1);
+   }
+
+   #[test]
+   fn type_2_without_capability() {
+      let mut data = sample().to_bytes();
+      data[40..54].iter_mut().for_each(|b| *b = 0);
+      let packet = Packet::parse(&data).unwrap();
+      assert_eq!(packet.orig, Addr::new(2, 5011, 1, 0));
+      assert_eq!(packet.dest, Addr::new(2, 5020, 100, 0));
+   }
+
+   #[test]
+   fn point_in_aux_net() {
+      let mut data = sample().to_bytes();
+      data[20..22].copy_from_slice(&0xffffu16.to_le_bytes());
+      data[38..40].copy_from_slice(&5011u16.to_le_bytes());
+      let packet = Packet::parse(&data).unwrap();
+      assert_eq!(packet.orig, Addr::new(2, 5011, 1, 5));
+   }
+
+   #[test]
+   fn broken_packets() {
+      assert!(Packet::parse(&[0; 10]).is_err());
+
+      let mut data = sample().to_bytes();
+      data[18] = 1;
+      assert!(Packet::parse(&data).is_err());
+
+      // Without the final zero
+      let mut data = sample().to_bytes();
+      data.truncate(data.len() - 2);
+      assert_eq!(Packet::parse(&data).unwrap().messages.len(), 1);
+   }
+}
@@ -0,0 +1,141 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+FidoNet style quoting. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+// Width of the text in FidoNet
+const WIDTH: usize = 79;
+
+// Initials like "AK" for "Artem Khomenko", the first and the last words
+pub fn initials(name: &str) -> String {
+   let mut words = name.split_whitespace().filter_map(|w| w.chars().find(|c| c.is_alphanumeric()));
+   let first = words.next();
+   let last = words.next_back();
+   first.into_iter().chain(last).collect()
+}
+
+// Greeting by the template of the area, "{}" is the first name
+pub fn greeting(template: &str, name: &str) -> String {
+   let first_name = name.split_whitespace().next().unwrap_or("All");
+   template.replace("{}", first_name)
+}
+
+// Prefix of already quoted line like " XY>> " up to the last '>'
+fn quote_prefix(line: &str) -> Option<usize> {
+   let pos = line.find('>')?;
+   let prefix = &line[..pos];
+   let is_initials = prefix.trim_start().len() <= 3 && prefix.trim_start().chars().all(char::is_alphanumeric);
+   if !is_initials {
+      return None;
+   }
+   Some(pos + line[pos..].chars().take_while(|c| *c == '>').count())
+}
+
+// Lines with " XY> " wrapped at the width, quoted ones get one more '>'
+pub fn quote(text: &str, initials: &str) -> String {
+   let prefix = format!(" {}> ", initials);
+   let mut lines = Vec::new();
+
+   for line in text.lines().map(str::trim_end) {
+      if line.is_empty() {
+         lines.push(String::new());
+         continue;
+      }
+
+      if let Some(pos) = quote_prefix(line) {
+         lines.push(format!("{}>{}", &line[..pos], &line[pos..]));
+         continue;
+      }
+
+      // Words to the width, too long words are split
+      let room = WIDTH - prefix.chars().count();
+      let mut current = String::new();
+      for word in line.split_whitespace() {
+         let mut word: Vec<char> = word.chars().collect();
+         while word.len() > room {
+            if !current.is_empty() {
+               lines.push(format!("{}{}", prefix, current));
+               current.clear();
+            }
+            let rest = word.split_off(room);
+            lines.push(format!("{}{}", prefix, word.iter().collect::<String>()));
+            word = rest;
+         }
+
+         let len = current.chars().count();
+         if len > 0 && len + 1 + word.len() > room {
+            lines.push(format!("{}{}", prefix, current));
+            current.clear();
+         }
+         if !current.is_empty() {
+            current.push(' ');
+         }
+         current.extend(word);
+      }
+      if !current.is_empty() {
+         lines.push(format!("{}{}", prefix, current));
+      }
+   }
+
+   // Without empty lines around
+   let start = lines.iter().position(|l| !l.is_empty()).unwrap_or(lines.len());
+   let end = lines.iter().rposition(|l| !l.is_empty()).map_or(start, |i| i + 1);
+   lines[start..end].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn names() {
+      assert_eq!(initials("Artem Khomenko"), "AK");
+      assert_eq!(initials("Artem Viktorovich Khomenko"), "AK");
+      assert_eq!(initials("Artem"), "A");
+      assert_eq!(initials("@artem"), "a");
+      assert_eq!(initials(""), "");
+      assert_eq!(greeting("Hello, {}!", "Artem Khomenko"), "Hello, Artem!");
+      assert_eq!(greeting("Hello, {}!", "Artem"), "Hello, Artem!");
+      assert_eq!(greeting("Hello, {}!", ""), "Hello, All!");
+   }
+
+   #[test]
+   fn wrapped_at_width() {
+      let text = vec!["word"; 40].join(" ");
+      let quoted = quote(&text, "AK");
+      let lines: Vec<&str> = quoted.lines().collect();
+
+      // 74 columns after " AK> " hold 15 words with 14 spaces
+      assert_eq!(lines.len(), 3);
+      assert_eq!(lines[0], format!(" AK> {}", vec!["word"; 15].join(" ")));
+      assert!(lines.iter().all(|line| line.starts_with(" AK> ") && line.chars().count() <= WIDTH));
+      assert_eq!(quoted.matches("word").count(), 40);
+   }
+
+   #[test]
+   fn long_word_split() {
+      let word = "x".repeat(200);
+      let quoted = quote(&format!("see {} end", word), "AK");
+      let lines: Vec<&str> = quoted.lines().collect();
+      assert_eq!(lines, vec![
+         String::from(" AK> see"),
+         format!(" AK> {}", "x".repeat(74)),
+         format!(" AK> {}", "x".repeat(74)),
+         format!(" AK> {} end", "x".repeat(52)),
+      ]);
+      assert!(lines.iter().all(|line| line.chars().count() <= WIDTH));
+   }
+
+   #[test]
+   fn requoted() {
+      let text = "\n AK> earlier words\nAK>> older\n\nnew\n\n";
+      assert_eq!(quote(text, "VP"), " AK>> earlier words\nAK>>> older\n\n VP> new");
+
+      // Not initials before '>'
+      assert_eq!(quote("a -> b", "VP"), " VP> a -> b");
+   }
+}
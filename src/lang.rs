@@ -0,0 +1,242 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Localization of bot texts. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use teloxide::types::User;
+
+use crate::database as db;
+
+// Languages of the interface, Russian is the default
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum Lang {
+   #[default]
+   Ru,
+   En,
+}
+
+impl Lang {
+   pub const ALL: [Lang; 2] = [Lang::Ru, Lang::En];
+
+   // Code for storing in the database and for Telegram
+   pub fn code(&self) -> &'static str {
+      match self {
+         Lang::Ru => "ru",
+         Lang::En => "en",
+      }
+   }
+
+   // Native name for the language selection button
+   pub fn name(&self) -> &'static str {
+      match self {
+         Lang::Ru => "Русский",
+         Lang::En => "English",
+      }
+   }
+
+   pub fn from_code(code: &str) -> Option<Lang> {
+      Lang::ALL.iter().find(|lang| lang.code() == code).copied()
+   }
+
+   // Language by language_code of Telegram, neighbours understand Russian
+   pub fn from_telegram(code: Option<&str>) -> Lang {
+      match code {
+         None => Lang::default(),
+         Some(code) => {
+            let code = code.split('-').next().unwrap_or_default();
+            match code {
+               "ru" | "uk" | "be" => Lang::Ru,
+               _ => Lang::En,
+            }
+         }
+      }
+   }
+}
+
+// Language of the user, the own setting takes precedence over Telegram one
+pub async fn user_lang(user: Option<&User>) -> Lang {
+   match user {
+      Some(user) => match db::user_lang(user.id).await {
+         Ok(Some(lang)) => lang,
+         Ok(None) => Lang::from_telegram(user.language_code.as_deref()),
+         Err(e) => {
+            // Not a reason to refuse the answer
+            log::info!("user_lang error for {}: {}", user.id, e);
+            Lang::from_telegram(user.language_code.as_deref())
+         }
+      },
+      None => Lang::default(),
+   }
+}
+
+// Identifiers of texts in the catalog
+#[derive(Clone, Copy)]
+pub enum Key {
+   CmdOrigin,
+   CmdList,
+   CmdInterval,
+   CmdLang,
+   CmdAreas,
+   DescrOrigin,
+   DescrList,
+   DescrInterval,
+   DescrHelp,
+   DescrCancel,
+   DescrLang,
+   DescrAreas,
+   ToStart,
+   NoUser,
+   Restarted,
+   Welcome,
+   UnknownCommand,
+   NoRights,
+   AvailableCommands,
+   Cancelled,
+   CurrentOrigin,
+   OriginUnchanged,
+   OriginSaved,
+   NoChats,
+   SelectChat,
+   UnknownChat,
+   AskInterval,
+   IntervalUnchanged,
+   IntervalSaved,
+   IntervalSaveError,
+   IntervalWrongInput,
+   ListEmpty,
+   SelectLang,
+   UnknownLang,
+   LangSaved,
+   TextPlease,
+   ReadOnly,
+   NoOrigin,
+   DatabaseError,
+   OriginSaveError,
+   LangSaveError,
+   CmdSave,
+   OriginTooLong,
+   OriginMultiline,
+   OriginControlChars,
+   OriginForbidden,
+   OriginPreview,
+   OriginConfirm,
+   NetmailHeader,
+   NetmailReplyHint,
+   NetmailSent,
+   NetmailError,
+   GatewayDisabled,
+   AreasList,
+   AreasNone,
+   AreaSaved,
+   AreaDeleted,
+   AreaNotFound,
+   AreaWrongInput,
+   AreaUnknownUplink,
+   DupesReport,
+}
+
+// Message catalog, Russian and English texts
+fn catalog(key: Key) -> (&'static str, &'static str) {
+   match key {
+      Key::CmdOrigin => ("Изменить ориджин", "Change origin"),
+      Key::CmdList => ("Список", "List"),
+      Key::CmdInterval => ("Интервал", "Interval"),
+      Key::CmdLang => ("Язык", "Language"),
+      Key::CmdAreas => ("Эхи", "Areas"),
+      Key::DescrOrigin => ("Изменить ориджин", "Change origin"),
+      Key::DescrList => ("Список пользователей", "List of users"),
+      Key::DescrInterval => ("Интервал напоминания адреса", "Address reminder interval"),
+      Key::DescrHelp => ("Список команд", "List of commands"),
+      Key::DescrCancel => ("Отмена и возврат в начало", "Cancel and return to the beginning"),
+      Key::DescrLang => ("Язык интерфейса", "Interface language"),
+      Key::DescrAreas => ("Эхоконференции и их чаты", "Echo areas and their chats"),
+      Key::ToStart => ("В начало", "To the beginning"),
+      Key::NoUser => ("Ошибка, нет пользователя", "Error, no user"),
+      Key::Restarted => ("Извините, бот был перезапущен.\n", "Sorry, the bot was restarted.\n"),
+      Key::Welcome => ("Добро пожаловать. Выберите команду на кнопке внизу или введите /help",
+         "Welcome. Choose a command on the button below or enter /help"),
+      Key::UnknownCommand => ("Неизвестная команда {}. Пожалуйста, выберите одну из команд внизу (если панель с кнопками скрыта, откройте её) или введите /help",
+         "Unknown command {}. Please choose one of the commands below (if the button panel is hidden, open it) or enter /help"),
+      Key::NoRights => ("Недостаточно прав", "Insufficient rights"),
+      Key::AvailableCommands => ("Доступные команды:", "Available commands:"),
+      Key::Cancelled => ("Действие отменено", "Action cancelled"),
+      Key::CurrentOrigin => ("Ваш текущий ориджин\n{}\nПожалуйста, введите текст для отображения после информации нодлиста\n Для отказа нажмите /",
+         "Your current origin\n{}\nPlease enter the text to display after the nodelist information\n To cancel press /"),
+      Key::OriginUnchanged => ("Ориджин не изменён", "Origin not changed"),
+      Key::OriginSaved => ("Ваш новый ориджин {} сохранён", "Your new origin {} saved"),
+      Key::NoChats => ("Бот пока не видел ни одного группового чата", "The bot has not seen any group chat yet"),
+      Key::SelectChat => ("Выберите чат для настройки или / для отмены", "Choose a chat to configure or / to cancel"),
+      Key::UnknownChat => ("Неизвестный чат {}. Пожалуйста, выберите один из чатов внизу", "Unknown chat {}. Please choose one of the chats below"),
+      Key::AskInterval => ("Время с момента последнего сообщения пользователя в чате {} для напоминания его адреса {} ч. Введите новый интервал в часах или / для отмены",
+         "Time since the last message of the user in the chat {} to remind the user's address is {} h. Enter a new interval in hours or / to cancel"),
+      Key::IntervalUnchanged => ("Интервал не изменён", "Interval not changed"),
+      Key::IntervalSaved => ("Новый интервал в {} ч. для чата {} сохранён", "New interval of {} h. for the chat {} saved"),
+      Key::IntervalSaveError => ("Ошибка сохранения интервала, обратитесь к разработчику", "Error saving the interval, contact the developer"),
+      Key::IntervalWrongInput => ("Неверный ввод, ожидалось целое число, например 1 для часового интервала, вы ввели {}. Интервал не изменён",
+         "Wrong input, an integer was expected, for example 1 for an hour interval, you entered {}. Interval not changed"),
+      Key::ListEmpty => ("Список пуст", "The list is empty"),
+      Key::SelectLang => ("Выберите язык интерфейса или / для отмены", "Choose the interface language or / to cancel"),
+      Key::UnknownLang => ("Неизвестный язык {}. Пожалуйста, выберите один из языков внизу", "Unknown language {}. Please choose one of the languages below"),
+      Key::LangSaved => ("Язык интерфейса изменён", "Interface language changed"),
+      Key::TextPlease => ("Текстовое сообщение, пожалуйста!", "Text message, please!"),
+      Key::ReadOnly => ("RO на часок. Не расстраивайся, {}!", "Read-only for an hour. Don't be upset, {}!"),
+      Key::NoOrigin => ("БОФА", "BOFA"),
+      Key::DatabaseError => ("Ошибка базы данных, попробуйте позже", "Database error, please try again later"),
+      Key::OriginSaveError => ("Ошибка сохранения ориджина, попробуйте позже. Ориджин не изменён", "Error saving the origin, please try again later. Origin not changed"),
+      Key::LangSaveError => ("Ошибка сохранения языка, попробуйте позже", "Error saving the language, please try again later"),
+      Key::CmdSave => ("Сохранить", "Save"),
+      Key::OriginTooLong => ("Слишком длинный ориджин, {} символов при допустимых {}. Введите текст покороче или / для отмены",
+         "The origin is too long, {} characters while {} allowed. Enter a shorter text or / to cancel"),
+      Key::OriginMultiline => ("Ориджин должен быть в одну строку. Введите текст без переводов строк или / для отмены",
+         "The origin must be a single line. Enter a text without line breaks or / to cancel"),
+      Key::OriginControlChars => ("Ориджин содержит недопустимые управляющие символы. Введите другой текст или / для отмены",
+         "The origin contains forbidden control characters. Enter another text or / to cancel"),
+      Key::OriginForbidden => ("Ориджин содержит недопустимые слова. Введите другой текст или / для отмены",
+         "The origin contains forbidden words. Enter another text or / to cancel"),
+      Key::OriginPreview => ("Так будет выглядеть напоминание:\n{}\n\nС полным адресом:\n{}\n\nНажмите «Сохранить» для подтверждения или / для отмены",
+         "The announcement will look like this:\n{}\n\nWith the full address:\n{}\n\nPress «Save» to confirm or / to cancel"),
+      Key::OriginConfirm => ("Нажмите «Сохранить» для подтверждения или / для отмены", "Press «Save» to confirm or / to cancel"),
+      Key::NetmailHeader => ("Нетмейл от {} ({}) для {} ({})", "Netmail from {} ({}) to {} ({})"),
+      Key::NetmailReplyHint => ("Ответьте на это сообщение, чтобы отправить ответ нетмейлом", "Reply to this message to answer by netmail"),
+      Key::NetmailSent => ("Ответ отправлен нетмейлом", "The reply is sent by netmail"),
+      Key::NetmailError => ("Ошибка отправки нетмейла, попробуйте позже", "Error sending netmail, please try again later"),
+      Key::GatewayDisabled => ("Шлюз в FidoNet отключён", "The FidoNet gateway is disabled"),
+      Key::AreasList => ("Эхоконференции:\n{}\n\nЧтобы добавить или изменить эху, введите «ЭХОТЭГ чат[/тема] [аплинк] [ro] [noquote] описание | приветствие», где {} в приветствии заменяется на имя, чтобы удалить — «-ЭХОТЭГ». Для выхода нажмите /",
+         "Echo areas:\n{}\n\nTo add or change an area enter «AREATAG chat[/topic] [uplink] [ro] [noquote] description | greeting», where {} in the greeting is replaced with the name, to delete it enter «-AREATAG». To exit press /"),
+      Key::AreasNone => ("нет", "none"),
+      Key::DupesReport => ("Дубликаты за {} дн.:\n{}", "Duplicates in {} days:\n{}"),
+      Key::AreaSaved => ("Эха сохранена:\n{}", "Area saved:\n{}"),
+      Key::AreaDeleted => ("Эха {} удалена", "Area {} deleted"),
+      Key::AreaNotFound => ("Эха {} не найдена", "Area {} not found"),
+      Key::AreaUnknownUplink => ("Мейлер не знает аплинк {}, добавьте его с хостом в FTN_LINKS", "The mailer does not know the uplink {}, add it with the host to FTN_LINKS"),
+      Key::AreaWrongInput => ("Неверный ввод {}, ожидалось например «R50.SYSOP -1001234567890 2:5011/1 ro Сисопы России»",
+         "Wrong input {}, expected for example «R50.SYSOP -1001234567890 2:5011/1 ro Sysops of Russia»"),
+   }
+}
+
+// Text in the language
+pub fn t(lang: Lang, key: Key) -> &'static str {
+   let (ru, en) = catalog(key);
+   match lang {
+      Lang::Ru => ru,
+      Lang::En => en,
+   }
+}
+
+// Text in the language with {} replaced by arguments in order
+pub fn tf(lang: Lang, key: Key, args: &[&(dyn Display + Sync)]) -> String {
+   let mut args = args.iter();
+   let mut parts = t(lang, key).split("{}");
+   let first = parts.next().unwrap_or_default().to_string();
+   parts.fold(first, |acc, part| match args.next() {
+      Some(arg) => format!("{}{}{}", acc, arg, part),
+      None => format!("{}{}", acc, part),
+   })
+}
+
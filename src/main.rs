@@ -7,92 +7,44 @@ http://www.gnu.org/licenses/gpl-3.0.html
 Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 =============================================================================== */
 
-use std::{convert::Infallible, env, net::SocketAddr};
+use std::env;
 use teloxide::{
    prelude::*, types::ChatPermissions,
-   dispatching::{update_listeners::{self, StatefulListener}, stop_token::AsyncStopToken}
+   dispatching::{dialogue::serializer::Json, ShutdownToken},
 };
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use warp::Filter;
-use reqwest::{StatusCode, Url};
-use native_tls::{TlsConnector};
-use postgres_native_tls::MakeTlsConnector;
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use chrono::{Duration, TimeZone, Utc};
 
 use crate::states::Dialogue;
+use crate::storage::{PgStorage, PgStorageError};
+use crate::pg_tls::TlsMode;
 
 
 mod states;
 mod database;
 mod settings;
+mod storage;
+mod lang;
+mod error;
+mod pg_tls;
+mod metrics;
+mod webhook;
+mod secret;
+mod tasks;
+mod ftn;
 use database::{self as db, };
 use settings::{self as set, };
+use lang::{Key, t, tf};
+use error::Error;
 
+// Heroku kills the process 30 seconds after SIGTERM
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
 
-async fn handle_rejection(error: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
-   log::error!("Cannot process the request due to: {:?}", error);
-   Ok(StatusCode::INTERNAL_SERVER_ERROR)
-}
-
-pub async fn webhook<'a>(bot: AutoSend<Bot>) -> impl update_listeners::UpdateListener<Infallible> {
-   // Heroku auto defines a port value
-   let teloxide_token = env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN env variable missing");
-   let port: u16 = env::var("PORT")
-       .expect("PORT env variable missing")
-       .parse()
-       .expect("PORT value to be integer");
-   // Heroku host example .: "heroku-ping-pong-bot.herokuapp.com"
-   let host = env::var("HOST").expect("have HOST env variable");
-   let path = format!("bot{}", teloxide_token);
-   let url =  Url::parse(&format!("https://{}/{}", host, path))
-   .unwrap();
-
-   bot.set_webhook(url).await.expect("Cannot setup a webhook");
-
-   let (tx, rx) = mpsc::unbounded_channel();
-
-   let server = warp::post()
-      .and(warp::path(path))
-      .and(warp::body::json())
-      .map(move |json: serde_json::Value| {
-         let try_parse = match serde_json::from_str(&json.to_string()) {
-            Ok(update) => Ok(update),
-            Err(error) => {
-                  log::error!(
-                     "Cannot parse an update.\nError: {:?}\nValue: {}\n\
-                     This is a bug in teloxide, please open an issue here: \
-                     https://github.com/teloxide/teloxide/issues.",
-                     error,
-                     json
-                  );
-                  Err(error)
-            }
-         };
-         if let Ok(update) = try_parse {
-            tx.send(Ok(update)).expect("Cannot send an incoming update from the webhook")
-         }
-
-         StatusCode::OK
-      })
-      .recover(handle_rejection);
-
-   let (stop_token, stop_flag) = AsyncStopToken::new_pair();
+// Heroku kills the process that does not bind the port in 60 seconds
+const DATABASE_WAIT: std::time::Duration = std::time::Duration::from_secs(15);
 
-   let addr = format!("0.0.0.0:{}", port).parse::<SocketAddr>().unwrap();
-   let server = warp::serve(server);
-   let (_addr, fut) = server.bind_with_graceful_shutdown(addr, stop_flag);
+// Delay before the next attempt to prepare the database
+const PREPARE_RETRY: std::time::Duration = std::time::Duration::from_secs(30);
 
-   // You might want to use serve.key_path/serve.cert_path methods here to
-   // setup a self-signed TLS certificate.
-
-   tokio::spawn(fut);
-   let stream = UnboundedReceiverStream::new(rx);
-
-   fn streamf<S, T>(state: &mut (S, T)) -> &mut S { &mut state.0 }
-
-   StatefulListener::new((stream, stop_token), streamf, |state: &mut (_, AsyncStopToken)| state.1.clone())
-}
 
 #[tokio::main]
 async fn main() {
@@ -106,52 +58,139 @@ async fn run() {
    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL env variable missing");
    log::info!("{}", database_url);
 
-   let connector = TlsConnector::builder()
-   .danger_accept_invalid_certs(true)
-   .build()
-   .unwrap();
-   let connector = MakeTlsConnector::new(connector);
-
-   // Откроем БД
-   let (client, connection) =
-      tokio_postgres::connect(&database_url, connector).await
-         .expect("Cannot connect to database");
-
-   // The connection object performs the actual communication with the database,
-   // so spawn it off to run on its own.
-   tokio::spawn(async move {
-      if let Err(e) = connection.await {
-         log::info!("Database connection error: {}", e);
-      }
-   });
-
-   // Сохраним доступ к БД
-   match db::DB.set(client) {
-      Ok(_) => log::info!("Database connected"),
-      _ => log::info!("Something wrong with database"),
+   // TLS settings in the same way as libpq, PGSSLMODE overrides sslmode of the URL
+   let tls_mode = env::var("PGSSLMODE").ok()
+   .map(|mode| mode.parse::<TlsMode>().expect("PGSSLMODE value to be disable, prefer, require, verify-ca or verify-full"));
+   let ca_path = env::var("PGSSLROOTCERT").ok();
+
+   // Откроем БД, соединения берутся из пула и восстанавливаются при обрыве
+   let pool_size = env::var("DATABASE_POOL_SIZE").ok()
+   .and_then(|v| v.parse().ok())
+   .unwrap_or(4);
+   db::connect(&database_url, tls_mode, ca_path.as_deref(), pool_size).expect("Cannot connect to database");
+
+   // Optional gateway to the echo area
+   ftn::gateway::init().expect("FTN settings fail");
+
+   // Heroku expects the port to be bound soon, so the late database is prepared in the background
+   if tokio::time::timeout(DATABASE_WAIT, db::wait_available()).await.is_ok() {
+      // Создадим таблицу в БД, если её ещё нет
+      db::check_database().await.expect("Cannot prepare database");
+      ftn::areas::init().await.expect("FTN areas fail");
+   } else {
+      log::info!("Database is not available, starting without it");
+      tokio::spawn(prepare_database());
    }
-
-   // Создадим таблицу в БД, если её ещё нет
-   db::check_database().await;
+   tokio::spawn(db::monitor());
 
    // Сохраним коды админов
    let admin1  = env::var("ADMIN_ID1").expect("ADMIN_ID1 env variable missing").parse().unwrap_or_default();
    let admin2 = env::var("ADMIN_ID2").expect("ADMIN_ID2 env variable missing").parse().unwrap_or_default();
    set::set_admins(admin1, admin2).expect("ADMIN_ID2 set fail");
 
+   // Optional filter for origins
+   let words = env::var("FORBIDDEN_WORDS").unwrap_or_default();
+   set::set_forbidden_words(&words).expect("FORBIDDEN_WORDS set fail");
+
    let bot = Bot::from_env().auto_send();
 
-   teloxide::dialogues_repl_with_listener(
-      bot.clone(),
-      |message, dialogue| async move {
-         handle_message(message, dialogue).await.expect("Something wrong with the bot!")
+   // Echomail from FidoNet to the chat
+   tokio::spawn(ftn::gateway::import_loop(bot.clone()));
+
+   // Mail exchange with the uplink
+   tokio::spawn(ftn::bso::pack_loop());
+   tokio::spawn(ftn::binkp::poll_loop());
+   tokio::spawn(ftn::binkp::listen_loop());
+   tokio::spawn(ftn::dupes::cleanup_loop());
+   tokio::spawn(ftn::gateway::serial_loop());
+
+   // Slash commands for the private dialogue
+   states::set_commands(&bot).await;
+
+   // Dialogues are stored in the database to survive restarts
+   let storage = PgStorage::new(Json);
+
+   let mut dispatcher = Dispatcher::new(bot.clone())
+   .messages_handler(DialogueDispatcher::with_storage(
+      |DialogueWithCx { cx, dialogue }: DialogueWithCx<AutoSend<Bot>, Message, Dialogue, PgStorageError<serde_json::Error>>| async move {
+         let dialogue = dialogue.unwrap_or_else(|e| {
+            log::info!("Error load dialogue: {}", e);
+            Dialogue::restarted()
+         });
+         metrics::UPDATES.inc();
+
+         // On failure stay in the previous state
+         let previous = dialogue.clone();
+         handle_message(cx, dialogue).await.unwrap_or_else(|e| {
+            log::info!("Error handle_message(): {}", e);
+            DialogueStage::Next(previous)
+         })
       },
-      webhook(bot).await
+      storage,
+   ));
+
+   // Heroku sends SIGTERM on every restart
+   tokio::spawn(shutdown_on_signal(dispatcher.shutdown_token()));
+
+   dispatcher.dispatch_with_listener(
+      webhook::listener(bot.clone()).await,
+      LoggingErrorHandler::with_custom_text("An error from the update listener"),
    )
-  .await;
+   .await;
+
+   // Handlers are finished, wait for the address lookups
+   if !tasks::drain(SHUTDOWN_TIMEOUT).await {
+      log::info!("Shutdown timeout, {} background tasks are lost", tasks::running());
+   }
+
+   // Optional, a new instance may already have set its own webhook
+   if matches!(env::var("WEBHOOK_DELETE_ON_SHUTDOWN").as_deref(), Ok("true") | Ok("1")) {
+      if let Err(e) = bot.delete_webhook().await {
+         log::info!("Error delete_webhook(): {}", e);
+      }
+   }
+
+   log::info!("N5011_bot stopped");
 }
 
-async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, dialogue: Dialogue) -> TransitionOut<Dialogue> {
+// Tables and areas once the database is up, until then the bot works without it
+async fn prepare_database() {
+   loop {
+      db::wait_available().await;
+      match db::check_database().await {
+         Ok(()) => break,
+         Err(e) => log::info!("Error check_database(): {}", e),
+      }
+      tokio::time::sleep(PREPARE_RETRY).await;
+   }
+
+   while let Err(e) = ftn::areas::init().await {
+      log::info!("Error areas init(): {}", e);
+      tokio::time::sleep(PREPARE_RETRY).await;
+   }
+}
+
+// Stop accepting updates on ^C or SIGTERM
+async fn shutdown_on_signal(token: ShutdownToken) {
+   #[cfg(unix)]
+   let terminate = async {
+      use tokio::signal::unix::{signal, SignalKind};
+      signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM").recv().await;
+   };
+   #[cfg(not(unix))]
+   let terminate = futures::future::pending::<()>();
+
+   tokio::select! {
+      _ = tokio::signal::ctrl_c() => log::info!("^C received, shutting down..."),
+      _ = terminate => log::info!("SIGTERM received, shutting down..."),
+   }
+
+   if let Err(e) = token.shutdown() {
+      log::info!("Error shutdown(): {}", e);
+   }
+}
+
+async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, dialogue: Dialogue) -> TransitionOut<Dialogue, Error> {
 
    let user = cx.update.from();
    if user.is_none() {
@@ -162,32 +201,81 @@ async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, dialogue: Dial
    // Collect info about update
    let user = user.unwrap();
    let user_id = user.id;
-   let time = cx.update.date;
+   let time = Utc.timestamp_opt(cx.update.date as i64, 0).unwrap();
    let text = String::from(cx.update.text().unwrap_or_default());
 
-   // Collect information and guaranteed to save the user in the database
-   let announcement = db::announcement(user_id, time).await;
-
    // Negative for chats, positive personal
    let chat_id = cx.update.chat_id();
 
    if chat_id > 0 {
-      if text == "" {
-         if let Err(e) = cx.answer("Текстовое сообщение, пожалуйста!").await {
+      // Guaranteed to save the user in the database, FSM will report problems itself
+      if let Err(e) = db::register_user(user_id, time).await {
+         log::info!("Error register_user() for {}: {}", user_id, e);
+      }
+
+      if text.is_empty() {
+         let lang = lang::user_lang(Some(user)).await;
+         if let Err(e) = cx.answer(t(lang, Key::TextPlease)).await {
             log::info!("Error main handle_message(): {}", e);
          }
          next(dialogue)
       } else {
+         // Reply to the delivered netmail goes back to FidoNet
+         if let Some(reply_to) = cx.update.reply_to_message().map(|msg| msg.id) {
+            let res = ftn::gateway::reply_netmail(user, chat_id, reply_to, &text, time).await;
+            if !matches!(res, Ok(false)) {
+               let key = match res {
+                  Err(e) => {
+                     log::info!("Error reply_netmail() for {}: {}", user_id, e);
+                     Key::NetmailError
+                  }
+                  _ => Key::NetmailSent,
+               };
+               let lang = lang::user_lang(Some(user)).await;
+               if let Err(e) = cx.answer(t(lang, key)).await {
+                  log::info!("Error main handle_message() netmail: {}", e);
+               }
+               return next(dialogue);
+            }
+         }
+
          // Private messages with FSM
          dialogue.react(cx, text).await
       }
    } else {
+      // Collect information and guaranteed to save the user in the database,
+      // moderation should work even without database
+      let announcement = if db::is_available() {
+         // Remember the chat for per-chat settings
+         let title = cx.update.chat.title().unwrap_or_default();
+         if let Err(e) = set::register_chat(chat_id, title).await {
+            log::info!("Error register_chat() for {}: {}", chat_id, e);
+         }
+
+         let lang = lang::user_lang(Some(user)).await;
+         db::announcement(user_id, chat_id, time, lang).await
+         .unwrap_or_else(|e| {
+            log::info!("Error announcement() for {} in {}: {}", user_id, chat_id, e);
+            None
+         })
+      } else {
+         None
+      };
+
+      // Copy the message to the echo area
+      if ftn::gateway::is_export_chat(chat_id) {
+         let reply_to = cx.update.reply_to_message();
+         if let Err(e) = ftn::gateway::export_user_message(user, chat_id, cx.update.id, reply_to, &text, time).await {
+            log::info!("Error export of the message from {}: {}", user_id, e);
+         }
+      }
+
       // Check moderate command
       let msg = cx.update.reply_to_message();
       if text == "[+]" && msg.is_some() && is_admin(&cx.requester, chat_id, user_id).await {
 
          // Extract the author and restrict
-         if let Some(from) = msg.unwrap().from() {
+         if let Some(from) = msg.and_then(|msg| msg.from()) {
             let res = cx.requester
             .restrict_chat_member(
                 chat_id,
@@ -195,20 +283,17 @@ async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, dialogue: Dial
                 ChatPermissions::default(),
             )
             // .until_date(cx.update.date as i64 + 3600i64)
-            .until_date(
-               DateTime::<Utc>::from_utc(
-                   NaiveDateTime::from_timestamp(cx.update.date as i64, 0),
-                   Utc,
-               ) + Duration::hours(1)
-            )
+            .until_date(time + Duration::hours(1))
             .await;
 
             // Notify chat members
             let res = if let Err(e) = res {
                cx.reply_to(format!("{}", e))
             } else {
+               metrics::MODERATIONS.inc();
                let name = from.username.clone().unwrap_or_default();
-               let text = format!("RO на часок. Не расстраивайся, {}!", name);
+               let lang = lang::user_lang(Some(from)).await;
+               let text = tf(lang, Key::ReadOnly, &[&name]);
                cx.requester.send_message(chat_id, text)
             };
             if let Err(e) = res.await {
@@ -219,8 +304,9 @@ async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, dialogue: Dial
 
       // Make announcement in chat if needs
       if let Some(announcement) = announcement {
-         if let Err(e) = cx.reply_to(announcement).await {
-            log::info!("Error main handle_message 3 (): {}", e);
+         match cx.reply_to(announcement).await {
+            Ok(_) => metrics::ANNOUNCEMENTS.inc(),
+            Err(e) => log::info!("Error main handle_message 3 (): {}", e),
          }
       }
 
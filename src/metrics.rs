@@ -0,0 +1,75 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Metrics for Prometheus. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+
+static REGISTRY: Lazy<Registry> = Lazy::new(|| Registry::new_custom(Some(String::from("n5011")), None).unwrap());
+
+pub static UPDATES: Lazy<IntCounter> = Lazy::new(|| {
+   register(IntCounter::new("updates_total", "Updates processed").unwrap())
+});
+
+pub static ANNOUNCEMENTS: Lazy<IntCounter> = Lazy::new(|| {
+   register(IntCounter::new("announcements_total", "Announcements sent to chats").unwrap())
+});
+
+pub static MODERATIONS: Lazy<IntCounter> = Lazy::new(|| {
+   register(IntCounter::new("moderations_total", "Users restricted by moderators").unwrap())
+});
+
+// Label result is success or failure
+pub static ADDR_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+   let opts = Opts::new("addr_lookups_total", "Requests of addresses from the nodelist service");
+   register(IntCounterVec::new(opts, &["result"]).unwrap())
+});
+
+pub static DB_LATENCY: Lazy<Histogram> = Lazy::new(|| {
+   let opts = HistogramOpts::new("db_latency_seconds", "Round trip of the database health check");
+   register(Histogram::with_opts(opts).unwrap())
+});
+
+pub static CHARSET_SUBSTITUTIONS: Lazy<IntCounter> = Lazy::new(|| {
+   register(IntCounter::new("charset_substitutions_total", "Texts with characters lost in conversion of charsets").unwrap())
+});
+
+// Label direction is import or export
+pub static DUPES: Lazy<IntCounterVec> = Lazy::new(|| {
+   let opts = Opts::new("dupes_total", "Duplicate echomail messages skipped");
+   register(IntCounterVec::new(opts, &["direction"]).unwrap())
+});
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: T) -> T {
+   if let Err(e) = REGISTRY.register(Box::new(metric.clone())) {
+      log::info!("Error register metric: {}", e);
+   }
+   metric
+}
+
+pub fn addr_lookup(success: bool) {
+   ADDR_LOOKUPS.with_label_values(&[if success { "success" } else { "failure" }]).inc();
+}
+
+// Text exposition format
+pub fn gather() -> String {
+   // Metrics appear in the output only after the first use
+   Lazy::force(&UPDATES);
+   Lazy::force(&ANNOUNCEMENTS);
+   Lazy::force(&MODERATIONS);
+   Lazy::force(&ADDR_LOOKUPS);
+   Lazy::force(&DB_LATENCY);
+   Lazy::force(&CHARSET_SUBSTITUTIONS);
+   Lazy::force(&DUPES);
+
+   let mut buffer = Vec::new();
+   if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+      log::info!("Error encode metrics: {}", e);
+   }
+   String::from_utf8(buffer).unwrap_or_default()
+}
@@ -0,0 +1,100 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+TLS for the database connection. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use native_tls::{Certificate, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use std::str::FromStr;
+use tokio_postgres::config::SslMode;
+
+use crate::error::{Error, Result};
+
+// Same meaning as sslmode of libpq
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TlsMode {
+   Disable,   // plain connection
+   Prefer,    // TLS if the server supports it, without verification
+   Require,   // TLS without verification
+   VerifyCa,  // TLS, the certificate is signed by a trusted CA
+   VerifyFull, // TLS, also the host name matches the certificate
+}
+
+impl FromStr for TlsMode {
+   type Err = Error;
+
+   fn from_str(s: &str) -> Result<Self> {
+      match s {
+         "disable" => Ok(TlsMode::Disable),
+         "prefer" => Ok(TlsMode::Prefer),
+         "require" => Ok(TlsMode::Require),
+         "verify-ca" => Ok(TlsMode::VerifyCa),
+         "verify-full" => Ok(TlsMode::VerifyFull),
+         _ => Err(Error::Settings("unknown sslmode, expected disable, prefer, require, verify-ca or verify-full")),
+      }
+   }
+}
+
+impl TlsMode {
+   // Mode from sslmode of the connection string
+   pub fn from_ssl_mode(mode: SslMode) -> Self {
+      match mode {
+         SslMode::Disable => TlsMode::Disable,
+         SslMode::Prefer => TlsMode::Prefer,
+         _ => TlsMode::Require,
+      }
+   }
+
+   // Mode for tokio_postgres, it does not verify anything by itself
+   pub fn ssl_mode(&self) -> SslMode {
+      match self {
+         TlsMode::Disable => SslMode::Disable,
+         TlsMode::Prefer => SslMode::Prefer,
+         _ => SslMode::Require,
+      }
+   }
+}
+
+// Connector with verification according to the mode and optional CA bundle in PEM
+pub fn connector(mode: TlsMode, ca_path: Option<&str>) -> Result<MakeTlsConnector> {
+   let mut builder = TlsConnector::builder();
+
+   match mode {
+      TlsMode::Disable | TlsMode::Prefer | TlsMode::Require => {
+         builder.danger_accept_invalid_certs(true);
+      }
+      TlsMode::VerifyCa => {
+         builder.danger_accept_invalid_hostnames(true);
+      }
+      TlsMode::VerifyFull => {}
+   }
+
+   if let Some(path) = ca_path {
+      for cert in load_certificates(path)? {
+         builder.add_root_certificate(cert);
+      }
+   }
+
+   let connector = builder.build()?;
+   Ok(MakeTlsConnector::new(connector))
+}
+
+// Every certificate of the bundle
+fn load_certificates(path: &str) -> Result<Vec<Certificate>> {
+   const END: &str = "-----END CERTIFICATE-----";
+
+   let pem = std::fs::read_to_string(path)?;
+   let certs = pem.split_inclusive(END)
+   .filter(|block| block.contains(END))
+   .map(|block| Certificate::from_pem(block.trim().as_bytes()))
+   .collect::<std::result::Result<Vec<_>, _>>()?;
+
+   if certs.is_empty() {
+      return Err(Error::Settings("no certificates in the CA bundle"));
+   }
+   Ok(certs)
+}
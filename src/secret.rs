@@ -0,0 +1,14 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Comparison of secrets. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+// Compare without early exit so the secret cannot be guessed by timing
+pub fn same(expected: &str, received: &str) -> bool {
+   expected.len() == received.len()
+   && expected.bytes().zip(received.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -7,15 +7,25 @@ http://www.gnu.org/licenses/gpl-3.0.html
 Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 =============================================================================== */
 
-use once_cell::sync::OnceCell;
-use std::sync::atomic::{AtomicU32, Ordering};
+use once_cell::sync::{OnceCell, Lazy};
+use std::sync::RwLock;
+use std::collections::HashMap;
+use chrono::Duration;
 
 use crate::database as db;
+use crate::error::{Error, Result};
 
 // Admin ID from environment
 static ADMINS: OnceCell<Admins> = OnceCell::new();
 
-static INTERVAL: OnceCell<AtomicU32> = OnceCell::new();
+// Default interval for new chats, loaded again on every preparation of the database
+static INTERVAL: RwLock<Option<Duration>> = RwLock::new(None);
+
+// Intervals for known chats
+static CHAT_INTERVALS: Lazy<RwLock<HashMap<i64, Duration>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+// Words not allowed in origin, optional
+static FORBIDDEN_WORDS: OnceCell<Vec<String>> = OnceCell::new();
 
 struct Admins {
    admin1: i64,
@@ -30,29 +40,81 @@ pub fn is_admin(user_id: i64) -> bool {
    }
 }
 
-pub fn set_admins(admin1: i64, admin2: i64) -> Result<(), ()> {
+pub fn admins() -> Vec<i64> {
+   match ADMINS.get() {
+      Some(a) => vec![a.admin1, a.admin2],
+      None => Vec::new(),
+   }
+}
+
+pub fn set_admins(admin1: i64, admin2: i64) -> Result<()> {
    let a = Admins {
       admin1,
       admin2,
    };
 
-   ADMINS.set(a).map_err(|_| ())
+   ADMINS.set(a).map_err(|_| Error::Settings("admins already set"))
 }
 
-pub async fn set_interval(v: i32) -> Result<(), ()> {
+pub async fn set_interval(chat_id: i64, v: Duration) -> Result<()> {
+   if !CHAT_INTERVALS.read().map_err(|_| Error::Settings("intervals lock poisoned"))?.contains_key(&chat_id) {
+      return Err(Error::Settings("unknown chat"));
+   }
+
+   // Database first, so that memory is consistent with it
+   db::update_interval(chat_id, v).await?;
+
    // Internal storage
-   let atomic = INTERVAL.get().ok_or(())?;
-   atomic.store(v as u32, Ordering::Relaxed);
+   init_chat_interval(chat_id, v)
+}
+
+pub fn interval(chat_id: i64) -> Duration {
+   CHAT_INTERVALS.read().unwrap()
+   .get(&chat_id)
+   .copied()
+   .unwrap_or_else(default_interval)
+}
 
-   // Database
-   db::update_interval(v).await
+pub fn default_interval() -> Duration {
+   INTERVAL.read().unwrap().unwrap()
 }
 
-pub fn interval() -> u32 {
-   let atomic = INTERVAL.get().unwrap();
-   atomic.load(Ordering::Relaxed)
+pub fn init_interval(v: Duration) -> Result<()> {
+   *INTERVAL.write().map_err(|_| Error::Settings("interval lock poisoned"))? = Some(v);
+   Ok(())
 }
 
-pub fn init_interval(v: i32) -> Result<(), ()> {
-   INTERVAL.set(AtomicU32::new(v as u32)).map_err(|_| ())
-}
\ No newline at end of file
+pub fn init_chat_interval(chat_id: i64, v: Duration) -> Result<()> {
+   CHAT_INTERVALS.write()
+   .map_err(|_| Error::Settings("intervals lock poisoned"))?
+   .insert(chat_id, v);
+   Ok(())
+}
+
+// Remember the group chat with the default interval, if it is new
+pub async fn register_chat(chat_id: i64, title: &str) -> Result<()> {
+   if CHAT_INTERVALS.read().map_err(|_| Error::Settings("intervals lock poisoned"))?.contains_key(&chat_id) {
+      return Ok(());
+   }
+
+   let v = default_interval();
+   db::save_new_chat(chat_id, title, v).await?;
+   init_chat_interval(chat_id, v)
+}
+
+// Comma separated list of words
+pub fn set_forbidden_words(words: &str) -> Result<()> {
+   let words = words.split(',')
+   .map(|w| w.trim().to_lowercase())
+   .filter(|w| !w.is_empty())
+   .collect();
+
+   FORBIDDEN_WORDS.set(words).map_err(|_| Error::Settings("forbidden words already set"))
+}
+
+pub fn has_forbidden_words(text: &str) -> bool {
+   let text = text.to_lowercase();
+   FORBIDDEN_WORDS.get()
+   .map(|words| words.iter().any(|w| text.contains(w.as_str())))
+   .unwrap_or(false)
+}
@@ -10,61 +10,178 @@ Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
 use derive_more::From;
 use teloxide_macros::{Transition, teloxide, };
 use teloxide::{prelude::*,
-   types::{ReplyMarkup, KeyboardButton, KeyboardMarkup, },
+   types::{ReplyMarkup, KeyboardButton, KeyboardMarkup, BotCommand, BotCommandScope, ChatId, },
+   RequestError,
 };
 use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
 
 use crate::database as db;
 use crate::settings as set;
+use crate::lang::{self, Lang, Key, t, tf};
+use crate::error::Error;
+use crate::ftn::{self, areas::{self, Area}};
 
 
-// FSM states
-#[derive(Transition, From, Clone)]
+// FSM states, persisted between restarts
+#[derive(Transition, From, Clone, Serialize, Deserialize)]
 pub enum Dialogue {
    Start(StartState),
    Command(CommandState),
    Origin(OriginState),
+   OriginConfirm(OriginConfirmState),
+   Chat(ChatState),
    Interval(IntervalState),
+   Lang(LangState),
+   Areas(AreasState),
 }
 
 impl Default for Dialogue {
    fn default() -> Self {
+       Self::Start(StartState { restarted: false })
+   }
+}
+
+impl Dialogue {
+   // Initial state when the stored one is lost
+   pub fn restarted() -> Self {
        Self::Start(StartState { restarted: true })
    }
 }
 
 // Commands for bot
+#[derive(Clone, Copy, PartialEq)]
 enum Command {
    Origin,  // change origin
    List, // List all users
    Interval, // Set time interval for announcements
+   Lang, // Interface language
+   Areas, // Echo areas of the gateway
+   Help, // Description of commands
+   Cancel, // Return to the beginning
+}
+
+impl Command {
+   const ALL: [Command; 7] = [Command::Origin, Command::List, Command::Interval, Command::Lang, Command::Areas, Command::Help, Command::Cancel];
+
+   // Slash command name registered in Telegram
+   fn name(&self) -> &'static str {
+      match self {
+         Command::Origin => "origin",
+         Command::List => "list",
+         Command::Interval => "interval",
+         Command::Lang => "lang",
+         Command::Areas => "areas",
+         Command::Help => "help",
+         Command::Cancel => "cancel",
+      }
+   }
+
+   fn description(&self, lang: Lang) -> &'static str {
+      let key = match self {
+         Command::Origin => Key::DescrOrigin,
+         Command::List => Key::DescrList,
+         Command::Interval => Key::DescrInterval,
+         Command::Lang => Key::DescrLang,
+         Command::Areas => Key::DescrAreas,
+         Command::Help => Key::DescrHelp,
+         Command::Cancel => Key::DescrCancel,
+      };
+      t(lang, key)
+   }
+
+   // Text on the button of the menu
+   fn caption(&self, lang: Lang) -> String {
+      match self {
+         Command::Origin => String::from(t(lang, Key::CmdOrigin)),
+         Command::List => String::from(t(lang, Key::CmdList)),
+         Command::Interval => String::from(t(lang, Key::CmdInterval)),
+         Command::Lang => String::from(t(lang, Key::CmdLang)),
+         Command::Areas => String::from(t(lang, Key::CmdAreas)),
+         _ => format!("/{}", self.name()),
+      }
+   }
+
+   // Available commands for the user
+   fn all(is_admin: bool) -> Vec<Command> {
+      if is_admin {
+         vec![Command::Origin, Command::Interval, Command::List, Command::Areas, Command::Lang, Command::Help, Command::Cancel]
+      } else {
+         vec![Command::Origin, Command::Lang, Command::Help, Command::Cancel]
+      }
+   }
 }
 
 impl TryFrom<&str> for Command {
    type Error = &'static str;
 
    fn try_from(s: &str) -> Result<Self, Self::Error> {
-      match s {
-         "Изменить ориджин" => Ok(Command::Origin),
-         "Список" => Ok(Command::List),
-         "Интервал" => Ok(Command::Interval),
-         _ => Err("Неизвестная команда"),
+      // Button caption in any language
+      let by_caption = Command::ALL.iter()
+      .find(|c| Lang::ALL.iter().any(|lang| c.caption(*lang) == s))
+      .copied();
+      if let Some(command) = by_caption {
+         return Ok(command);
       }
+
+      // Slash command, possibly with the bot name like /help@N5011_bot
+      let name = s.strip_prefix('/').ok_or("Unknown command")?;
+      let name = name.split('@').next().unwrap_or_default();
+      Command::ALL.iter()
+      .find(|c| c.name() == name)
+      .copied()
+      .ok_or("Unknown command")
    }
 }
 
-impl From<Command> for String {
-   fn from(c: Command) -> String {
-      match c {
-         Command::Origin => String::from("Изменить ориджин"),
-         Command::List => String::from("Список"),
-         Command::Interval => String::from("Интервал"),
+// Register slash commands in Telegram, admins get the extended list
+pub async fn set_commands(bot: &AutoSend<Bot>) {
+   let commands = |is_admin, lang| Command::all(is_admin)
+   .into_iter()
+   .map(move |c| BotCommand::new(c.name(), c.description(lang)));
+
+   for lang in Lang::ALL.iter() {
+      // Russian is for everyone without a dedicated list
+      let code = if *lang == Lang::default() { String::default() } else { String::from(lang.code()) };
+
+      let res = bot.set_my_commands(commands(false, *lang))
+      .scope(BotCommandScope::AllPrivateChats)
+      .language_code(code.clone())
+      .await;
+      if let Err(e) = res {
+         log::info!("Error set_commands(): {}", e);
+      }
+
+      for admin in set::admins() {
+         let res = bot.set_my_commands(commands(true, *lang))
+         .scope(BotCommandScope::Chat(ChatId::Id(admin)))
+         .language_code(code.clone())
+         .await;
+         if let Err(e) = res {
+            log::info!("Error set_commands() for {}: {}", admin, e);
+         }
       }
    }
 }
 
+// Either the button or the command to go back
+fn is_cancel(ans: &str) -> bool {
+   ans == "/" || Command::try_from(ans) == Ok(Command::Cancel)
+}
+
+// Slash command in the middle of the dialogue goes to the command handler,
+// the cancel is handled by the state itself
+fn is_command(ans: &str) -> bool {
+   ans.starts_with('/') && !is_cancel(ans)
+}
+
+// Telegram sends /start on the first contact, afterwards it is the same as /help
+fn is_start(ans: &str) -> bool {
+   ans.split('@').next() == Some("/start")
+}
+
 // Frequently used menu
-fn one_button_markup(label: &'static str) -> ReplyMarkup {
+fn one_button_markup(label: &str) -> ReplyMarkup {
    let keyboard = vec![vec![KeyboardButton::new(label)]];
    let keyboard = KeyboardMarkup::new(keyboard)
    .resize_keyboard(true);
@@ -72,33 +189,55 @@ fn one_button_markup(label: &'static str) -> ReplyMarkup {
    ReplyMarkup::Keyboard(keyboard)
 }
 
+// Menu with one button per row
+fn column_markup<I: IntoIterator<Item = String>>(labels: I) -> ReplyMarkup {
+   let keyboard: Vec<Vec<KeyboardButton>> = labels.into_iter()
+   .map(|label| vec![KeyboardButton::new(label)])
+   .collect();
+   let keyboard = KeyboardMarkup::new(keyboard)
+   .resize_keyboard(true);
+
+   ReplyMarkup::Keyboard(keyboard)
+}
+
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct StartState {
    restarted: bool,
 }
 
 #[teloxide(subtransition)]
-async fn start(state: StartState, cx: TransitionIn<AutoSend<Bot>>, _ans: String,) -> TransitionOut<Dialogue> {
+async fn start(state: StartState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
    // Extract user id
    let user = cx.update.from();
+   let lang = lang::user_lang(user).await;
    if user.is_none() {
-      cx.answer("Error, no user").await?;
+      cx.answer(t(lang, Key::NoUser)).await?;
       return next(StartState { restarted: false });
    }
 
    // For admin and regular users there is different interface
    let user_id = user.unwrap().id;
    let is_admin = set::is_admin(user_id);
+   let state_command = CommandState { user_id, is_admin, lang };
+
+   // Slash command is executed at once, without the menu
+   if ans.starts_with('/') && Command::try_from(ans.as_str()).is_ok() {
+      return run_command(state_command, cx, ans).await;
+   }
 
    // Prepare menu
    let commands = if is_admin {
-      vec![KeyboardButton::new(Command::Origin),
-      // KeyboardButton::new(Command::List),
-      KeyboardButton::new(Command::Interval),
+      vec![KeyboardButton::new(Command::Origin.caption(lang)),
+      KeyboardButton::new(Command::List.caption(lang)),
+      KeyboardButton::new(Command::Interval.caption(lang)),
+      KeyboardButton::new(Command::Areas.caption(lang)),
+      KeyboardButton::new(Command::Lang.caption(lang)),
       ]
    } else {
-      vec![KeyboardButton::new(Command::Origin)]
+      vec![KeyboardButton::new(Command::Origin.caption(lang)),
+      KeyboardButton::new(Command::Lang.caption(lang)),
+      ]
    };
 
    let keyboard = KeyboardMarkup::new(vec![commands])
@@ -106,38 +245,59 @@ async fn start(state: StartState, cx: TransitionIn<AutoSend<Bot>>, _ans: String,
 
    let markup = ReplyMarkup::Keyboard(keyboard);
 
-   let info = String::from(if state.restarted { "Извините, бот был перезапущен.\n" } else {""});
-   let info = info + "Добро пожаловать. Выберите команду на кнопке внизу";
+   let info = String::from(if state.restarted { t(lang, Key::Restarted) } else {""});
+   let info = info + t(lang, Key::Welcome);
 
    cx.answer(info)
    .reply_markup(markup)
    .await?;
-   next(CommandState { user_id, is_admin })
+   next(state_command)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CommandState {
    user_id: i64,
    is_admin: bool,
+   #[serde(default)]
+   lang: Lang,
 }
 
 #[teloxide(subtransition)]
-async fn select_command(state: CommandState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue> {
+async fn select_command(state: CommandState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   run_command(state, cx, ans).await
+}
+
+// Also called from the start state for slash commands
+async fn run_command(state: CommandState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.lang;
+
    // Parse text from user
-   let command = Command::try_from(ans.as_str());
-   if command.is_err() {
-      cx.answer(format!("Неизвестная команда {}. Пожалуйста, выберите одну из команд внизу (если панель с кнопками скрыта, откройте её)", ans)).await?;
+   let command = if is_start(&ans) { Ok(Command::Help) } else { Command::try_from(ans.as_str()) };
+   let command = match command {
+      Ok(command) => command,
+      Err(_) => {
+         cx.answer(tf(lang, Key::UnknownCommand, &[&ans])).await?;
+
+         // Stay in previous state
+         return next(state)
+      }
+   };
 
-      // Stay in previous state
+   // Check access rights
+   if !Command::all(state.is_admin).contains(&command) {
+      cx.answer(t(lang, Key::NoRights)).await?;
       return next(state)
    }
 
    // Handle commands
-   match command.unwrap() {
+   match command {
       Command::Origin => {
          // Collect info about update
-         let info = db::user_descr(state.user_id).await;
-         let info = format!("Ваш текущий ориджин\n{}\nПожалуйста, введите текст для отображения после информации нодлиста\n Для отказа нажмите /", info);
+         let info = match db::user_descr(state.user_id).await {
+            Ok(info) => info,
+            Err(e) => return database_error(state, cx, e).await,
+         };
+         let info = tf(lang, Key::CurrentOrigin, &[&info]);
 
          cx.answer(info)
          .reply_markup(one_button_markup("/"))
@@ -147,74 +307,438 @@ async fn select_command(state: CommandState, cx: TransitionIn<AutoSend<Bot>>, an
       }
 
       Command::Interval => {
-         let info = format!("Время с момента последнего сообщения пользователя для напоминания его адреса {} ч. Введите новый интервал в часах или / для отмены", set::interval() / 3600);
+         // Settings are per chat, so the admin should choose one
+         let mut chats = match db::chats().await {
+            Ok(chats) => chats,
+            Err(e) => return database_error(state, cx, e).await,
+         };
+         match chats.len() {
+            0 => {
+               cx.answer(t(lang, Key::NoChats))
+               .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+               .await?;
+
+               next(StartState { restarted: false })
+            }
+            1 => {
+               let chat = chats.remove(0);
+               ask_interval(&cx, lang, &chat).await?;
+               next(IntervalState { state, chat })
+            }
+            _ => {
+               let labels = chats.iter()
+               .map(|chat| chat.title.clone())
+               .chain(std::iter::once(String::from("/")));
+
+               cx.answer(t(lang, Key::SelectChat))
+               .reply_markup(column_markup(labels))
+               .await?;
+
+               next(ChatState { state, chats })
+            }
+         }
+      }
 
-         cx.answer(info)
+      Command::List => {
+         let list = match db::users_list().await {
+            Ok(list) => list,
+            Err(e) => return database_error(state, cx, e).await,
+         };
+         let info = if list.is_empty() {
+            String::from(t(lang, Key::ListEmpty))
+         } else {
+            list.join("\n")
+         };
+
+         // Telegram limits the length of the message
+         let mut lines = info.lines().peekable();
+         while lines.peek().is_some() {
+            let mut part = String::new();
+            while let Some(line) = lines.peek() {
+               if !part.is_empty() && part.len() + line.len() >= 4000 {
+                  break;
+               }
+               part.push_str(line);
+               part.push('\n');
+               lines.next();
+            }
+            cx.answer(part).await?;
+         }
+
+         next(state)
+      }
+
+      Command::Lang => {
+         let labels = Lang::ALL.iter()
+         .map(|lang| String::from(lang.name()))
+         .chain(std::iter::once(String::from("/")));
+
+         cx.answer(t(lang, Key::SelectLang))
+         .reply_markup(column_markup(labels))
+         .await?;
+
+         next(LangState { state })
+      }
+
+      Command::Areas => {
+         if ftn::gateway::config().is_none() {
+            cx.answer(t(lang, Key::GatewayDisabled)).await?;
+            return next(state)
+         }
+
+         cx.answer(areas_list(lang).await)
          .reply_markup(one_button_markup("/"))
          .await?;
 
-         next(IntervalState { state })
+         next(AreasState { state })
+      }
+
+      Command::Help => {
+         let info = Command::all(state.is_admin)
+         .iter()
+         .fold(String::from(t(lang, Key::AvailableCommands)), |acc, c| format!("{}\n/{} - {}", acc, c.name(), c.description(lang)));
+
+         cx.answer(info).await?;
+         next(state)
+      }
+
+      Command::Cancel => {
+         cx.answer(t(lang, Key::Cancelled))
+         .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+         .await?;
+
+         next(StartState { restarted: false })
       }
-      _ => next(state),
    }
 }
 
+// Report the failure and go back to the beginning
+async fn database_error(state: CommandState, cx: TransitionIn<AutoSend<Bot>>, e: Error) -> TransitionOut<Dialogue, Error> {
+   log::info!("Database error for {}: {}", state.user_id, e);
+
+   cx.answer(t(state.lang, Key::DatabaseError))
+   .reply_markup(one_button_markup(t(state.lang, Key::ToStart)))
+   .await?;
+
+   next(StartState { restarted: false })
+}
+
 // #[derive(Generic)]
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct OriginState {
    state: CommandState,
 }
 
 #[teloxide(subtransition)]
-async fn origin(state: OriginState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue> {
-   let info = if ans == "/" {
-      String::from("Ориджин не изменён")
+async fn origin(state: OriginState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_cancel(&ans) {
+      cx.answer(t(lang, Key::OriginUnchanged))
+      .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+      .await?;
+
+      return next(StartState { restarted: false })
+   }
+
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   // Stay in the state until the correct input
+   if let Err(info) = check_origin(&ans, lang) {
+      cx.answer(info)
+      .reply_markup(one_button_markup("/"))
+      .await?;
+
+      return next(state)
+   }
+
+   // Show how it will look
+   let (short, full) = match db::announcement_preview(state.state.user_id, &ans, lang).await {
+      Ok(preview) => preview,
+      Err(e) => return database_error(state.state, cx, e).await,
+   };
+   let labels = vec![String::from(t(lang, Key::CmdSave)), String::from("/")];
+
+   cx.answer(tf(lang, Key::OriginPreview, &[&short, &full]))
+   .reply_markup(column_markup(labels))
+   .await?;
+
+   next(OriginConfirmState { state: state.state, descr: ans })
+}
+
+// Limit of the column in the database
+const ORIGIN_MAX_LEN: usize = 100;
+
+// Text of the error if the origin is not suitable
+fn check_origin(descr: &str, lang: Lang) -> Result<(), String> {
+   let len = descr.chars().count();
+   if len > ORIGIN_MAX_LEN {
+      Err(tf(lang, Key::OriginTooLong, &[&len, &ORIGIN_MAX_LEN]))
+   } else if descr.contains(&['\n', '\r'][..]) {
+      Err(String::from(t(lang, Key::OriginMultiline)))
+   } else if descr.chars().any(char::is_control) {
+      Err(String::from(t(lang, Key::OriginControlChars)))
+   } else if set::has_forbidden_words(descr) {
+      Err(String::from(t(lang, Key::OriginForbidden)))
    } else {
+      Ok(())
+   }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OriginConfirmState {
+   state: CommandState,
+   descr: String,
+}
+
+#[teloxide(subtransition)]
+async fn origin_confirm(state: OriginConfirmState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   let info = if is_cancel(&ans) {
+      String::from(t(lang, Key::OriginUnchanged))
+   } else if ans == t(lang, Key::CmdSave) {
       // Save to database
-      db::update_user_descr(state.state.user_id, &ans).await;
+      match db::update_user_descr(state.state.user_id, &state.descr).await {
+         Ok(()) => tf(lang, Key::OriginSaved, &[&state.descr]),
+         Err(e) => {
+            log::info!("origin_confirm error for {}: {}", state.state.user_id, e);
+            String::from(t(lang, Key::OriginSaveError))
+         }
+      }
+   } else {
+      cx.answer(t(lang, Key::OriginConfirm)).await?;
 
-      format!("Ваш новый ориджин {} сохранён", ans)
+      // Stay in previous state
+      return next(state)
    };
 
    cx.answer(info)
-   .reply_markup(one_button_markup("В начало"))
+   .reply_markup(one_button_markup(t(lang, Key::ToStart)))
    .await?;
-   
+
    next(StartState { restarted: false })
 }
 
+// Prompt for a new interval of the chat
+async fn ask_interval(cx: &TransitionIn<AutoSend<Bot>>, lang: Lang, chat: &db::Chat) -> Result<(), RequestError> {
+   let info = tf(lang, Key::AskInterval, &[&chat.title, &set::interval(chat.chat_id).num_hours()]);
+
+   cx.answer(info)
+   .reply_markup(one_button_markup("/"))
+   .await?;
+
+   Ok(())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChatState {
+   state: CommandState,
+   chats: Vec<db::Chat>,
+}
+
+#[teloxide(subtransition)]
+async fn select_chat(state: ChatState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_cancel(&ans) {
+      cx.answer(t(lang, Key::IntervalUnchanged))
+      .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+      .await?;
+
+      return next(StartState { restarted: false })
+   }
+
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   match state.chats.iter().find(|chat| chat.title == ans) {
+      Some(chat) => {
+         let chat = chat.clone();
+         ask_interval(&cx, lang, &chat).await?;
+         next(IntervalState { state: state.state, chat })
+      }
+      None => {
+         cx.answer(tf(lang, Key::UnknownChat, &[&ans])).await?;
+
+         // Stay in previous state
+         next(state)
+      }
+   }
+}
+
 // #[derive(Generic)]
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct IntervalState {
    state: CommandState,
+   chat: db::Chat,
 }
 
 #[teloxide(subtransition)]
-async fn interval(state: IntervalState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue> {
-   let info = if ans == "/" {
-      String::from("Интервал не изменён")
+async fn interval(state: IntervalState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   let info = if is_cancel(&ans) {
+      String::from(t(lang, Key::IntervalUnchanged))
    } else {
       // Check access rights
       if !state.state.is_admin {
-         String::from("Недостаточно прав")
+         String::from(t(lang, Key::NoRights))
       } else {
          // Checking the correctness of the input
          match ans.parse::<u32>() {
             Ok(v) => {
                // Save to database
-               if let Ok(()) = set::set_interval(v as i32 *3600).await {
-                  format!("Новый интервал в {} ч. сохранён", ans)
-               } else {
-                  String::from("Ошибка сохранения интервала, обратитесь к разработчику")
+               match set::set_interval(state.chat.chat_id, chrono::Duration::hours(v as i64)).await {
+                  Ok(()) => tf(lang, Key::IntervalSaved, &[&ans, &state.chat.title]),
+                  Err(e) => {
+                     log::info!("interval error for {}: {}", state.chat.chat_id, e);
+                     String::from(t(lang, Key::IntervalSaveError))
+                  }
                }
             },
-            _ =>  format!("Неверный ввод, ожидалось целое число, например 1 для часового интервала, вы ввели {}. Интервал не изменён", ans),
+            _ =>  tf(lang, Key::IntervalWrongInput, &[&ans]),
          }
       }
    };
 
    cx.answer(info)
-   .reply_markup(one_button_markup("В начало"))
+   .reply_markup(one_button_markup(t(lang, Key::ToStart)))
    .await?;
    next(StartState { restarted: false })
 }
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LangState {
+   state: CommandState,
+}
+
+#[teloxide(subtransition)]
+async fn select_lang(state: LangState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_cancel(&ans) {
+      cx.answer(t(lang, Key::Cancelled))
+      .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+      .await?;
+
+      return next(StartState { restarted: false })
+   }
+
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   match Lang::ALL.iter().find(|lang| lang.name() == ans) {
+      Some(new_lang) => {
+         // Save to database and answer already in the new language
+         if let Err(e) = db::update_user_lang(state.state.user_id, *new_lang).await {
+            log::info!("select_lang error for {}: {}", state.state.user_id, e);
+            cx.answer(t(lang, Key::LangSaveError))
+            .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+            .await?;
+
+            return next(StartState { restarted: false })
+         }
+
+         cx.answer(t(*new_lang, Key::LangSaved))
+         .reply_markup(one_button_markup(t(*new_lang, Key::ToStart)))
+         .await?;
+
+         next(StartState { restarted: false })
+      }
+      None => {
+         cx.answer(tf(lang, Key::UnknownLang, &[&ans])).await?;
+
+         // Stay in previous state
+         next(state)
+      }
+   }
+}
+
+// Current areas with the number of duplicates and the hint for the input
+async fn areas_list(lang: Lang) -> String {
+   let list = areas::all();
+   let list = if list.is_empty() {
+      String::from(t(lang, Key::AreasNone))
+   } else {
+      list.iter().map(Area::to_string).collect::<Vec<_>>().join("\n")
+   };
+
+   // Counters are kept in the database for the retention period
+   let hits = db::dupe_hits().await.unwrap_or_else(|e| {
+      log::info!("Error dupe_hits(): {}", e);
+      Vec::new()
+   });
+   let hits = if hits.is_empty() {
+      String::from(t(lang, Key::AreasNone))
+   } else {
+      hits.iter().map(|(area, count)| format!("{}: {}", area, count)).collect::<Vec<_>>().join("\n")
+   };
+   let days = ftn::gateway::config().map_or(0, |config| config.dupe_days);
+
+   let list = format!("{}\n\n{}", list, tf(lang, Key::DupesReport, &[&days, &hits]));
+   // The placeholder of the greeting stays in the hint
+   tf(lang, Key::AreasList, &[&list, &"{}"])
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AreasState {
+   state: CommandState,
+}
+
+#[teloxide(subtransition)]
+async fn edit_areas(state: AreasState, cx: TransitionIn<AutoSend<Bot>>, ans: String,) -> TransitionOut<Dialogue, Error> {
+   let lang = state.state.lang;
+   if is_cancel(&ans) {
+      cx.answer(t(lang, Key::Cancelled))
+      .reply_markup(one_button_markup(t(lang, Key::ToStart)))
+      .await?;
+
+      return next(StartState { restarted: false })
+   }
+
+   if is_command(&ans) {
+      return run_command(state.state, cx, ans).await;
+   }
+
+   // Check access rights
+   let config = match ftn::gateway::config() {
+      Some(config) if state.state.is_admin => config,
+      _ => {
+         cx.answer(t(lang, Key::NoRights)).await?;
+         return next(StartState { restarted: false })
+      }
+   };
+
+   let info = if let Some(tag) = ans.strip_prefix('-') {
+      match areas::delete(tag.trim()).await {
+         Ok(true) => tf(lang, Key::AreaDeleted, &[&tag.trim()]),
+         Ok(false) => tf(lang, Key::AreaNotFound, &[&tag.trim()]),
+         Err(e) => return database_error(state.state, cx, e).await,
+      }
+   } else {
+      match Area::parse(&ans, config.uplink) {
+         // Otherwise the mail of the area would stay in the outbound
+         Some(area) if !config.is_reachable(area.uplink) => tf(lang, Key::AreaUnknownUplink, &[&area.uplink]),
+         Some(area) => {
+            let info = tf(lang, Key::AreaSaved, &[&area]);
+            if let Err(e) = areas::save(area).await {
+               return database_error(state.state, cx, e).await;
+            }
+            info
+         }
+         None => tf(lang, Key::AreaWrongInput, &[&ans]),
+      }
+   };
+
+   // Stay in the state for the next change
+   cx.answer(info).await?;
+   next(state)
+}
@@ -0,0 +1,75 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Persistent dialogue storage. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use derive_more::Display;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt::Debug, sync::Arc};
+use teloxide::dispatching::dialogue::{Serializer, Storage};
+
+use crate::database as db;
+use crate::error::Error;
+
+// Dialogue states in the same database as users, so they survive restarts
+pub struct PgStorage<S> {
+   serializer: S,
+}
+
+#[derive(Debug, Display)]
+pub enum PgStorageError<SE> {
+   #[display(fmt = "dialogue serialization error: {}", _0)]
+   Serde(SE),
+
+   #[display(fmt = "{}", _0)]
+   Database(Error),
+
+   #[display(fmt = "dialogue not found")]
+   DialogueNotFound,
+}
+
+impl<S> PgStorage<S> {
+   pub fn new(serializer: S) -> Arc<Self> {
+      Arc::new(Self { serializer })
+   }
+}
+
+impl<S, D> Storage<D> for PgStorage<S>
+where
+   S: Send + Sync + Serializer<D> + 'static,
+   D: Send + Serialize + DeserializeOwned + 'static,
+   <S as Serializer<D>>::Error: Debug,
+{
+   type Error = PgStorageError<<S as Serializer<D>>::Error>;
+
+   fn remove_dialogue(self: Arc<Self>, chat_id: i64) -> BoxFuture<'static, Result<(), Self::Error>> {
+      Box::pin(async move {
+         match db::remove_dialogue(chat_id).await {
+            Ok(0) => Err(PgStorageError::DialogueNotFound),
+            Ok(_) => Ok(()),
+            Err(e) => Err(PgStorageError::Database(e)),
+         }
+      })
+   }
+
+   fn update_dialogue(self: Arc<Self>, chat_id: i64, dialogue: D) -> BoxFuture<'static, Result<(), Self::Error>> {
+      Box::pin(async move {
+         let data = self.serializer.serialize(&dialogue).map_err(PgStorageError::Serde)?;
+         db::update_dialogue(chat_id, &data).await.map_err(PgStorageError::Database)
+      })
+   }
+
+   fn get_dialogue(self: Arc<Self>, chat_id: i64) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+      Box::pin(async move {
+         db::dialogue(chat_id).await
+         .map_err(PgStorageError::Database)?
+         .map(|data| self.serializer.deserialize(&data).map_err(PgStorageError::Serde))
+         .transpose()
+      })
+   }
+}
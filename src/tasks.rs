@@ -0,0 +1,59 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Background tasks. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{future::Future, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+
+// Tasks outside of the dispatcher, they should finish before exit
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static FINISHED: Lazy<Notify> = Lazy::new(Notify::new);
+
+// Decrements the counter even if the task panics
+struct Running;
+
+impl Drop for Running {
+   fn drop(&mut self) {
+      if RUNNING.fetch_sub(1, Ordering::SeqCst) == 1 {
+         FINISHED.notify_waiters();
+      }
+   }
+}
+
+pub fn spawn<F>(task: F)
+where
+   F: Future<Output = ()> + Send + 'static,
+{
+   RUNNING.fetch_add(1, Ordering::SeqCst);
+   let running = Running;
+   tokio::spawn(async move {
+      let _running = running;
+      task.await
+   });
+}
+
+pub fn running() -> usize {
+   RUNNING.load(Ordering::SeqCst)
+}
+
+// Wait for all tasks, false on timeout
+pub async fn drain(timeout: Duration) -> bool {
+   tokio::time::timeout(timeout, async {
+      loop {
+         // Subscribe before the check to not miss the notification
+         let finished = FINISHED.notified();
+         if running() == 0 {
+            break;
+         }
+         finished.await;
+      }
+   })
+   .await
+   .is_ok()
+}
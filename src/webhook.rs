@@ -0,0 +1,426 @@
+/* ===============================================================================
+Bot to support Telegram channel of 2:5011 Fidonet
+Webhook server. 18 October 2026.
+----------------------------------------------------------------------------
+Licensed under the terms of the GPL version 3.
+http://www.gnu.org/licenses/gpl-3.0.html
+Copyright (c) 2020 by Artem Khomenko _mag12@yahoo.com.
+=============================================================================== */
+
+use std::{convert::Infallible, env, net::{IpAddr, SocketAddr}};
+use teloxide::{
+   prelude::*,
+   dispatching::{update_listeners::{self, StatefulListener}, stop_token::{AsyncStopToken, AsyncStopFlag, StopToken}},
+};
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_native_tls::TlsAcceptor;
+use warp::{Filter, Rejection, Reply, reject::Reject};
+use reqwest::{StatusCode, Url, multipart};
+use hyper::{header::HeaderValue, service::Service};
+use ipnet::IpNet;
+
+use crate::database as db;
+use crate::error::Result;
+use crate::metrics;
+use crate::secret;
+
+// Updates are small, anything bigger is not from Telegram
+const BODY_LIMIT: u64 = 1024 * 1024;
+
+// Networks of Telegram webhook requests, https://core.telegram.org/bots/webhooks
+const TELEGRAM_NETWORKS: [&str; 2] = ["149.154.160.0/20", "91.108.4.0/22"];
+
+const SECRET_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+// Time for the TLS handshake of one connection
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Certificate and key for the server without a router in front, like a plain VPS
+struct TlsSettings {
+   cert_path: String,
+   acceptor: TlsAcceptor,
+   self_signed: bool,
+}
+
+// Stops the server, then the updates stream ends after the received ones
+#[derive(Clone)]
+struct ListenerStop {
+   server: AsyncStopToken,
+   updates: AsyncStopToken,
+}
+
+impl StopToken for ListenerStop {
+   fn stop(self) {
+      self.server.stop();
+      self.updates.stop();
+   }
+}
+
+// Request did not pass the checks of secret or address
+#[derive(Debug)]
+struct Forbidden;
+impl Reject for Forbidden {}
+
+async fn handle_rejection(error: Rejection) -> std::result::Result<impl warp::Reply, Infallible> {
+   let status = if error.is_not_found() {
+      StatusCode::NOT_FOUND
+   } else if error.find::<Forbidden>().is_some() {
+      StatusCode::FORBIDDEN
+   } else if error.find::<warp::reject::PayloadTooLarge>().is_some() {
+      StatusCode::PAYLOAD_TOO_LARGE
+   } else if error.find::<warp::reject::LengthRequired>().is_some() {
+      StatusCode::LENGTH_REQUIRED
+   } else if error.find::<warp::body::BodyDeserializeError>().is_some() {
+      StatusCode::BAD_REQUEST
+   } else if error.find::<warp::reject::MethodNotAllowed>().is_some() {
+      StatusCode::METHOD_NOT_ALLOWED
+   } else {
+      log::error!("Cannot process the request due to: {:?}", error);
+      StatusCode::INTERNAL_SERVER_ERROR
+   };
+   Ok(status)
+}
+
+// Optional secret for the X-Telegram-Bot-Api-Secret-Token header
+fn secret_from_env() -> Option<String> {
+   let secret = env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty())?;
+
+   // Telegram restrictions
+   let valid = secret.len() <= 256
+   && secret.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+   assert!(valid, "WEBHOOK_SECRET to be up to 256 characters A-Z, a-z, 0-9, _ and -");
+   Some(secret)
+}
+
+// Optional list of networks, "telegram" means the networks of Telegram
+fn allowed_from_env() -> Option<Vec<IpNet>> {
+   let value = env::var("WEBHOOK_ALLOWED_IPS").ok().filter(|s| !s.is_empty())?;
+
+   let list = if value == "telegram" { TELEGRAM_NETWORKS.join(",") } else { value };
+   let nets = list.split(',')
+   .map(|s| {
+      let s = s.trim();
+      s.parse::<IpNet>()
+      .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+      .expect("WEBHOOK_ALLOWED_IPS to be telegram or comma-separated networks")
+   })
+   .collect();
+   Some(nets)
+}
+
+// Optional TLS, the key is PKCS#8 in PEM as openssl req -newkey generates
+fn tls_from_env() -> Option<TlsSettings> {
+   let cert_path = env::var("WEBHOOK_CERT").ok().filter(|s| !s.is_empty())?;
+   let key_path = env::var("WEBHOOK_KEY").expect("WEBHOOK_KEY env variable missing for WEBHOOK_CERT");
+   let self_signed = matches!(env::var("WEBHOOK_SELF_SIGNED").as_deref(), Ok("true") | Ok("1"));
+
+   let acceptor = tls_acceptor(&cert_path, &key_path).expect("Cannot load the webhook certificate");
+   Some(TlsSettings { cert_path, acceptor, self_signed })
+}
+
+fn tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+   let cert = std::fs::read(cert_path)?;
+   let key = std::fs::read(key_path)?;
+   let identity = native_tls::Identity::from_pkcs8(&cert, &key)?;
+   let acceptor = native_tls::TlsAcceptor::new(identity)?;
+   Ok(TlsAcceptor::from(acceptor))
+}
+
+// Behind a router like Heroku, set WEBHOOK_TRUSTED_PROXY, otherwise anyone can forge X-Forwarded-For
+fn trusted_proxy_from_env() -> bool {
+   matches!(env::var("WEBHOOK_TRUSTED_PROXY").as_deref(), Ok("true") | Ok("1"))
+}
+
+// The client address is the last in X-Forwarded-For of the trusted proxy
+fn client_ip(remote: Option<SocketAddr>, forwarded: Option<String>, trust_forwarded: bool) -> Option<IpAddr> {
+   forwarded
+   .filter(|_| trust_forwarded)
+   .and_then(|list| list.rsplit(',').next().and_then(|s| s.trim().parse().ok()))
+   .or_else(|| remote.map(|addr| addr.ip()))
+}
+
+// Checks of the request before reading the body
+fn guard(secret: Option<String>, allowed: Option<Vec<IpNet>>, trust_forwarded: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+   warp::addr::remote()
+   .and(warp::header::optional::<String>("x-forwarded-for"))
+   .and(warp::header::optional::<String>(SECRET_HEADER))
+   .and_then(move |remote: Option<SocketAddr>, forwarded: Option<String>, token: Option<String>| {
+      let secret_ok = match (&secret, token) {
+         (Some(secret), Some(token)) => secret::same(secret, &token),
+         (Some(_), None) => false,
+         (None, _) => true,
+      };
+
+      let ip = client_ip(remote, forwarded, trust_forwarded);
+      let ip_ok = match &allowed {
+         Some(nets) => ip.is_some_and(|ip| nets.iter().any(|net| net.contains(&ip))),
+         None => true,
+      };
+
+      if !secret_ok || !ip_ok {
+         log::info!("Webhook request rejected from {:?}, secret ok: {}, address ok: {}", ip, secret_ok, ip_ok);
+      }
+
+      async move {
+         if secret_ok && ip_ok { Ok(()) } else { Err(warp::reject::custom(Forbidden)) }
+      }
+   })
+   .untuple_one()
+}
+
+// Metrics for the scraper with WEBHOOK_SECRET as the bearer token,
+// without the secret only the addresses of WEBHOOK_ALLOWED_IPS are checked
+fn metrics_guard(secret: Option<String>, allowed: Option<Vec<IpNet>>, trust_forwarded: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+   warp::addr::remote()
+   .and(warp::header::optional::<String>("x-forwarded-for"))
+   .and(warp::header::optional::<String>("authorization"))
+   .and_then(move |remote: Option<SocketAddr>, forwarded: Option<String>, auth: Option<String>| {
+      let ip = client_ip(remote, forwarded, trust_forwarded);
+      let ok = match (&secret, &allowed) {
+         (Some(secret), _) => auth.as_deref()
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .is_some_and(|token| secret::same(secret, token.trim())),
+         (None, Some(nets)) => ip.is_some_and(|ip| nets.iter().any(|net| net.contains(&ip))),
+         (None, None) => true,
+      };
+
+      if !ok {
+         log::info!("Metrics request rejected from {:?}", ip);
+      }
+
+      async move {
+         if ok { Ok(()) } else { Err(warp::reject::custom(Forbidden)) }
+      }
+   })
+   .untuple_one()
+}
+
+// teloxide does not know the secret_token parameter, so call the method directly
+async fn set_webhook(bot: &AutoSend<Bot>, token: &str, url: Url, secret: Option<&str>, certificate: Option<&str>) -> Result<()> {
+   if secret.is_none() && certificate.is_none() {
+      bot.set_webhook(url).await?;
+      return Ok(());
+   }
+
+   let mut form = multipart::Form::new().text("url", url.to_string());
+   if let Some(secret) = secret {
+      form = form.text("secret_token", secret.to_string());
+   }
+
+   // Self-signed certificate to let Telegram trust it
+   if let Some(path) = certificate {
+      let cert = std::fs::read(path)?;
+      form = form.part("certificate", multipart::Part::bytes(cert).file_name("cert.pem"));
+   }
+
+   let method = format!("https://api.telegram.org/bot{}/setWebhook", token);
+   reqwest::Client::new()
+   .post(&method)
+   .multipart(form)
+   .send()
+   .await?
+   .error_for_status()?;
+   Ok(())
+}
+
+// Serve the filter over TLS until the stop flag
+async fn serve_tls<F>(filter: F, listener: TcpListener, acceptor: TlsAcceptor, stop_flag: AsyncStopFlag)
+where
+   F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+   F::Extract: Reply,
+{
+   tokio::pin!(stop_flag);
+
+   loop {
+      let (tcp, peer) = tokio::select! {
+         _ = &mut stop_flag => break,
+         res = listener.accept() => match res {
+            Ok(conn) => conn,
+            Err(e) => {
+               log::info!("Error accept webhook connection: {}", e);
+               continue;
+            }
+         },
+      };
+
+      let acceptor = acceptor.clone();
+      let service = warp::service(filter.clone());
+
+      tokio::spawn(async move {
+         let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+               log::info!("Error TLS handshake with {}: {}", peer, e);
+               return;
+            }
+            Err(_) => {
+               log::info!("Error TLS handshake with {}: timeout", peer);
+               return;
+            }
+         };
+
+         // warp does not see the address of such connections, so pass it
+         // the same way as a router, overwriting anything from the client
+         let ip = HeaderValue::from_str(&peer.ip().to_string()).ok();
+         let service = hyper::service::service_fn(move |mut req| {
+            if let Some(ip) = &ip {
+               req.headers_mut().insert("x-forwarded-for", ip.clone());
+            }
+            let mut service = service.clone();
+            service.call(req)
+         });
+
+         if let Err(e) = hyper::server::conn::Http::new().serve_connection(stream, service).await {
+            log::info!("Error webhook connection with {}: {}", peer, e);
+         }
+      });
+   }
+}
+
+pub async fn listener(bot: AutoSend<Bot>) -> impl update_listeners::UpdateListener<Infallible> {
+   // Heroku auto defines a port value
+   let teloxide_token = env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN env variable missing");
+   let port: u16 = env::var("PORT")
+       .expect("PORT env variable missing")
+       .parse()
+       .expect("PORT value to be integer");
+   // Heroku host example .: "heroku-ping-pong-bot.herokuapp.com"
+   let host = env::var("HOST").expect("have HOST env variable");
+   let path = format!("bot{}", teloxide_token);
+   let secret = secret_from_env();
+   let allowed = allowed_from_env();
+   let tls = tls_from_env();
+
+   // With own TLS the header is set from the connection, see serve_tls()
+   let trust_forwarded = tls.is_some() || trusted_proxy_from_env();
+   if allowed.is_some() && !trust_forwarded {
+      log::info!("WEBHOOK_ALLOWED_IPS checks the address of the connection, behind a router set WEBHOOK_TRUSTED_PROXY");
+   }
+
+   if secret.is_none() && allowed.is_none() {
+      log::info!("/metrics is public, set WEBHOOK_SECRET and scrape it with the bearer token");
+   }
+
+   // With own TLS Telegram connects directly, so to our port (443, 80, 88 or 8443)
+   let url = if tls.is_some() && port != 443 {
+      Url::parse(&format!("https://{}:{}/{}", host, port, path))
+   } else {
+      Url::parse(&format!("https://{}/{}", host, path))
+   }
+   .unwrap();
+
+   let certificate = tls.as_ref()
+   .filter(|tls| tls.self_signed)
+   .map(|tls| tls.cert_path.as_str());
+   set_webhook(&bot, &teloxide_token, url, secret.as_deref(), certificate).await.expect("Cannot setup a webhook");
+
+   let (tx, rx) = mpsc::unbounded_channel();
+
+   // Service endpoints for monitoring
+   let healthz = warp::get()
+      .and(warp::path("healthz"))
+      .and(warp::path::end())
+      .map(|| StatusCode::OK);
+
+   let ready_bot = bot.clone();
+   let readyz = warp::get()
+      .and(warp::path("readyz"))
+      .and(warp::path::end())
+      .and_then(move || {
+         let bot = ready_bot.clone();
+         async move { Ok::<_, Infallible>(readiness(&bot).await) }
+      });
+
+   let metrics = warp::get()
+      .and(warp::path("metrics"))
+      .and(warp::path::end())
+      .and(metrics_guard(secret.clone(), allowed.clone(), trust_forwarded))
+      .map(metrics::gather);
+
+   let server = warp::post()
+      .and(warp::path(path))
+      .and(warp::path::end())
+      .and(guard(secret, allowed, trust_forwarded))
+      .and(warp::body::content_length_limit(BODY_LIMIT))
+      .and(warp::body::json())
+      .map(move |json: serde_json::Value| {
+         let update = match serde_json::from_value(json.clone()) {
+            Ok(update) => update,
+            Err(error) => {
+               // Answer OK anyway, otherwise Telegram will repeat the same update
+               log::error!(
+                  "Cannot parse an update.\nError: {:?}\nValue: {}\n\
+                  This is a bug in teloxide, please open an issue here: \
+                  https://github.com/teloxide/teloxide/issues.",
+                  error,
+                  json
+               );
+               return StatusCode::OK;
+            }
+         };
+
+         // The dispatcher is stopping, Telegram will deliver the update later
+         if let Err(e) = tx.send(Ok(update)) {
+            log::info!("Cannot send an incoming update from the webhook: {}", e);
+            return StatusCode::SERVICE_UNAVAILABLE;
+         }
+
+         StatusCode::OK
+      })
+      .or(healthz)
+      .or(readyz)
+      .or(metrics)
+      .recover(handle_rejection);
+
+   let (server_token, server_flag) = AsyncStopToken::new_pair();
+   let (updates_token, updates_flag) = AsyncStopToken::new_pair();
+   let stop_token = ListenerStop { server: server_token, updates: updates_token };
+
+   let addr = format!("0.0.0.0:{}", port).parse::<SocketAddr>().unwrap();
+   match tls {
+      Some(tls) => {
+         let listener = TcpListener::bind(addr).await.expect("Cannot bind the webhook address");
+         tokio::spawn(serve_tls(server, listener, tls.acceptor, server_flag));
+      }
+      None => {
+         let (_addr, fut) = warp::serve(server).bind_with_graceful_shutdown(addr, server_flag);
+         tokio::spawn(fut);
+      }
+   }
+
+   // After the stop new updates are refused and Telegram repeats them later,
+   // already accepted ones are still processed
+   let stream = Box::pin(futures::stream::unfold((rx, Box::pin(updates_flag), false), |(mut rx, mut flag, closed)| async move {
+      if !closed {
+         tokio::select! {
+            update = rx.recv() => return update.map(|update| (update, (rx, flag, false))),
+            _ = &mut flag => rx.close(),
+         }
+      }
+      rx.recv().await.map(|update| (update, (rx, flag, true)))
+   }));
+
+   fn streamf<S, T>(state: &mut (S, T)) -> &mut S { &mut state.0 }
+
+   StatefulListener::new((stream, stop_token), streamf, |state: &mut (_, ListenerStop)| state.1.clone())
+}
+
+// Both the database and Telegram should respond
+async fn readiness(bot: &AutoSend<Bot>) -> warp::reply::WithStatus<String> {
+   let timeout = std::time::Duration::from_secs(5);
+
+   let db = match tokio::time::timeout(timeout, db::ping()).await {
+      Ok(Ok(())) => String::from("ok"),
+      Ok(Err(e)) => e.to_string(),
+      Err(_) => String::from("timeout"),
+   };
+
+   let telegram = match tokio::time::timeout(timeout, bot.get_me().send()).await {
+      Ok(Ok(_)) => String::from("ok"),
+      Ok(Err(e)) => e.to_string(),
+      Err(_) => String::from("timeout"),
+   };
+
+   let status = if db == "ok" && telegram == "ok" { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+   warp::reply::with_status(format!("database: {}\ntelegram: {}\n", db, telegram), status)
+}